@@ -0,0 +1,2 @@
+DROP INDEX users_name_unique ON users;
+ALTER TABLE users MODIFY name TEXT NOT NULL;
@@ -0,0 +1,3 @@
+-- 用户名唯一，重复创建时返回 409
+ALTER TABLE users MODIFY name VARCHAR(191) NOT NULL;
+CREATE UNIQUE INDEX users_name_unique ON users (name);
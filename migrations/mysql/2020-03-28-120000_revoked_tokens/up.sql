@@ -0,0 +1,10 @@
+-- 已撤销的 JWT（Bearer token），过期后由进程删除
+-- expires_at 使用 DATETIME，避免 MySQL 为 TIMESTAMP 列自动添加 ON UPDATE
+CREATE TABLE revoked_tokens (
+  jti VARCHAR(64) NOT NULL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  expires_at DATETIME NOT NULL,
+  revoked_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX revoked_tokens_expires_at ON revoked_tokens (expires_at);
@@ -0,0 +1,16 @@
+-- 角色拥有的权限，权限名为 `资源:操作`，`*` 表示所有权限
+CREATE TABLE role_permissions (
+  role VARCHAR(64) NOT NULL,
+  permission VARCHAR(64) NOT NULL,
+  PRIMARY KEY (role, permission)
+);
+
+-- 用户的角色
+CREATE TABLE user_roles (
+  user_id BIGINT NOT NULL,
+  role VARCHAR(64) NOT NULL,
+  PRIMARY KEY (user_id, role)
+);
+
+INSERT INTO role_permissions (role, permission) VALUES ('admin', '*');
+INSERT INTO role_permissions (role, permission) VALUES ('moderator', 'posts:delete');
@@ -0,0 +1,9 @@
+-- 服务端 session，cookie 中只保存 id，过期后由进程删除
+-- expires_at 使用 DATETIME，避免 MySQL 为 TIMESTAMP 列自动添加 ON UPDATE
+CREATE TABLE sessions (
+  id VARCHAR(64) NOT NULL PRIMARY KEY,
+  data TEXT NOT NULL,
+  expires_at DATETIME NOT NULL
+);
+
+CREATE INDEX sessions_expires_at ON sessions (expires_at);
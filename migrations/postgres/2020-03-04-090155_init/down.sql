@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE posts;
+DROP TABLE users;
+DROP FUNCTION users_set_updated_at();
@@ -0,0 +1,32 @@
+-- Your SQL goes here
+CREATE TABLE posts (
+  id BIGSERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  title VARCHAR(256) NOT NULL,
+  body TEXT NOT NULL,
+  published BOOLEAN NOT NULL DEFAULT FALSE
+);
+COMMENT ON COLUMN posts.title IS '标题';
+COMMENT ON COLUMN posts.body IS '内容';
+COMMENT ON COLUMN posts.published IS '是否发布';
+
+CREATE TABLE users (
+  id BIGSERIAL PRIMARY KEY,
+  name TEXT NOT NULL,
+  hair_color TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- PostgreSQL 没有 ON UPDATE CURRENT_TIMESTAMP，使用触发器代替
+CREATE FUNCTION users_set_updated_at() RETURNS trigger AS $$
+BEGIN
+  IF NEW IS DISTINCT FROM OLD AND NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at THEN
+    NEW.updated_at := CURRENT_TIMESTAMP;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_updated_at BEFORE UPDATE ON users
+FOR EACH ROW EXECUTE PROCEDURE users_set_updated_at();
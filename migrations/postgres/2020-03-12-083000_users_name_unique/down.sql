@@ -0,0 +1,2 @@
+DROP INDEX users_name_unique;
+ALTER TABLE users ALTER COLUMN name TYPE TEXT;
@@ -0,0 +1,3 @@
+-- 用户名唯一，重复创建时返回 409
+ALTER TABLE users ALTER COLUMN name TYPE VARCHAR(191);
+CREATE UNIQUE INDEX users_name_unique ON users (name);
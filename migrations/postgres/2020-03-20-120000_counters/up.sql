@@ -0,0 +1,5 @@
+-- 命名计数器的持久化，由进程定时写入
+CREATE TABLE counters (
+  name VARCHAR(64) NOT NULL PRIMARY KEY,
+  value BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1,9 @@
+-- 已撤销的 JWT（Bearer token），过期后由进程删除
+CREATE TABLE revoked_tokens (
+  jti VARCHAR(64) NOT NULL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  expires_at TIMESTAMP NOT NULL,
+  revoked_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX revoked_tokens_expires_at ON revoked_tokens (expires_at);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER users_updated_at;
+DROP TABLE posts;
+DROP TABLE users;
@@ -0,0 +1,2 @@
+-- 用户名唯一，重复创建时返回 409
+CREATE UNIQUE INDEX users_name_unique ON users (name);
@@ -0,0 +1,2 @@
+-- 需要 SQLite 3.35 及以上版本
+ALTER TABLE users DROP COLUMN password_hash;
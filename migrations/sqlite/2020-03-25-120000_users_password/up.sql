@@ -0,0 +1,2 @@
+-- 密码哈希（PHC 格式），为空的用户不能通过密码登录
+ALTER TABLE users ADD COLUMN password_hash VARCHAR(255);
@@ -0,0 +1,8 @@
+-- 服务端 session，cookie 中只保存 id，过期后由进程删除
+CREATE TABLE sessions (
+  id VARCHAR(64) NOT NULL PRIMARY KEY,
+  data TEXT NOT NULL,
+  expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX sessions_expires_at ON sessions (expires_at);
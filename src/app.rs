@@ -0,0 +1,659 @@
+//! 应用工厂：注册所有路由，main 和测试共用同一套服务树
+//!
+//! ```ignore
+//! let deps = AppDeps::new(settings, pool)?;
+//! HttpServer::new(move || App::new().configure(|cfg| configure_app(cfg, &deps)))
+//! ```
+
+use actix::{Actor, Addr};
+use actix_web::{error, web, FromRequest, HttpResponse, Scope};
+
+use crate::authz;
+use crate::chat::ChatServer;
+use crate::counter::Counters;
+use crate::events::PostEvents;
+use crate::middleware::Authorize;
+use crate::session::{self, Sessions};
+use crate::settings::SettingsError;
+use crate::shutdown::Shutdown;
+use crate::token::Jwt;
+use crate::trace::Tracer;
+use crate::handlers::basic;
+use crate::handlers::extractor::{self, JsonInfo};
+use crate::handlers::health::{self, StartTime};
+use crate::handlers::{admin, auth, block, counters, debug, errors, metrics, posts, responder, users, ws};
+use crate::{AppError, Metrics, PoolConnection, Settings};
+
+/// 路由依赖的共享状态，所有 worker 共用
+#[derive(Clone)]
+pub struct AppDeps {
+    pub settings: Settings,
+    pub pool: PoolConnection,
+    pub counters: Counters,
+    pub metrics: Metrics,
+    pub tracer: Tracer,
+    pub jwt: Jwt,
+    pub sessions: Sessions,
+    /// 只启动一个，所有 worker 共用
+    pub chat: Addr<ChatServer>,
+    /// 文章变更通知，只启动一个，所有 worker 共用
+    pub events: Addr<PostEvents>,
+    pub shutdown: Shutdown,
+    pub started: StartTime,
+}
+
+impl AppDeps {
+    /// JWT 密钥无法读取或解析时返回错误
+    pub fn new(settings: Settings, pool: PoolConnection) -> Result<Self, SettingsError> {
+        Ok(AppDeps {
+            tracer: Tracer::new(settings.trace.clone()),
+            jwt: Jwt::new(&settings.jwt)?,
+            // 随机生成的签名密钥需要在所有 worker 之间共享
+            sessions: Sessions::new(&settings.session, session::new_store(settings.session.store, pool.clone())),
+            chat: ChatServer::new().start(),
+            events: PostEvents::new(&settings.events).start(),
+            counters: Counters::new(&settings.counters),
+            settings,
+            pool,
+            metrics: Metrics::new(),
+            shutdown: Shutdown::new(),
+            started: StartTime::now(),
+        })
+    }
+}
+
+/// 注册共享状态和所有路由，中间件由调用方通过 `App::wrap` 添加
+pub fn configure_app(cfg: &mut web::ServiceConfig, deps: &AppDeps) {
+    let json_limit = deps.settings.server.json_limit;
+
+    // 这种方式注册的数据不会被 scope 的 app_data 覆盖，handler 中使用 web::Data<T> 读取
+    cfg.data(deps.settings.clone())
+        .data(deps.pool.clone())
+        .data(deps.counters.clone())
+        .data(deps.metrics.clone())
+        .data(deps.tracer.clone())
+        .data(deps.jwt.clone())
+        .data(deps.shutdown.clone())
+        .data(deps.chat.clone())
+        .data(deps.settings.ws.clone())
+        .data(deps.events.clone())
+        .data(deps.settings.events.clone())
+        .data(deps.started)
+        .route("/", web::get().to(basic::index))
+        .route("/again/", web::get().to(basic::index2))
+        .service(basic::index3)
+        .service(
+            web::scope("/app")
+                .route("/index.html", web::get().to(basic::index))
+                .service(basic::index3)
+        )
+        .route("/app_state", web::get().to(basic::app_state));
+    // ServiceConfig 没有 configure 方法，直接调用
+    basic::config(cfg);
+    cfg.service(web::scope("/app3").configure(basic::scoped_config))
+        .service(
+            web::scope("/responder")
+                .route("/str", web::get().to(responder::responder_str))
+                .route("/string", web::get().to(responder::responder_string))
+                .route("/impl_responder", web::get().to(responder::responder_impl_responder))
+                .route("/custom_responder", web::get().to(responder::responder_custom_responder))
+                .route("/stream", web::get().to(responder::responder_stream_responder))
+                .route("/sse", web::get().to(responder::responder_sse))
+                .route("/either", web::get().to(responder::responder_either_responder))
+        )
+        .service(
+            web::scope("/extractor")
+                // 配置 Json Extractor，scope 的 app_data 会覆盖 App 级别的配置
+                .app_data(web::Json::<JsonInfo>::configure(|cfg| {
+                    cfg.limit(json_limit).error_handler(|err, _req| {
+                        error::InternalError::from_response(
+                            err,
+                            HttpResponse::Conflict().finish(),
+                        )
+                        .into()
+                    })
+                }))
+                .route("/multiple/{p1}/{p2}", web::get().to(extractor::extractor_multiple))
+                .route("/path/{user_id}/{friend}", web::get().to(extractor::extractor_path))
+                .route("/manual_path/{user_id}/{friend}", web::get().to(extractor::extractor_manual_path))
+                .route("/query", web::get().to(extractor::extractor_query))
+                .route("/json", web::post().to(extractor::extractor_json))
+                .route("/form", web::post().to(extractor::extractor_form))
+        )
+        .service(
+            web::scope("/error")
+                .route("/custom", web::get().to(errors::error_custom))
+                .route("/enum", web::get().to(errors::error_enum))
+                .route("/helper", web::get().to(errors::error_helper))
+        )
+        .route("/metrics", web::get().to(metrics::metrics))
+        .route("/healthz", web::get().to(health::healthz))
+        .route("/readyz", web::get().to(health::readyz))
+        .route("/status", web::get().to(health::status))
+        .service(
+            api_scope("/counters", json_limit)
+                .route("", web::get().to(counters::counters_list))
+                .route("/{name}", web::get().to(counters::counters_get))
+                .route("/{name}/increment", web::post().to(counters::counters_increment))
+                .service(
+                    web::resource("/{name}/reset")
+                        .wrap(Authorize::new(authz::role(authz::ADMIN)))
+                        .route(web::post().to(counters::counters_reset))
+                )
+        )
+        .service(
+            api_scope("/debug", json_limit)
+                .wrap(Authorize::new(authz::role(authz::ADMIN)))
+                .route("/requests", web::get().to(debug::debug_requests))
+                .route("/requests", web::delete().to(debug::debug_requests_clear))
+                .route("/trace", web::get().to(debug::debug_trace))
+                .route("/trace", web::put().to(debug::debug_trace_update))
+        )
+        .service(
+            web::scope("/ws")
+                .route("/echo", web::get().to(ws::ws_echo))
+                .route("/chat", web::get().to(ws::ws_chat))
+                .route("/rpc", web::get().to(ws::ws_rpc))
+                .route("/posts", web::get().to(ws::ws_posts))
+        )
+        .service(web::scope("/block").configure(block::config))
+        .service(
+            api_scope("/admin", json_limit)
+                .wrap(Authorize::new(authz::role(authz::ADMIN)))
+                .route("/users/{id}/roles", web::get().to(admin::user_roles))
+                .route("/users/{id}/roles/{role}", web::put().to(admin::user_roles_add))
+                .route("/users/{id}/roles/{role}", web::delete().to(admin::user_roles_remove))
+        )
+        .service(
+            api_scope("/auth", json_limit)
+                .route("/register", web::post().to(auth::register))
+                .route("/login", web::post().to(auth::login))
+                .route("/logout", web::post().to(auth::logout))
+                .route("/me", web::get().to(auth::me))
+                .route("/token", web::post().to(auth::token))
+                .route("/token/refresh", web::post().to(auth::token_refresh))
+                .route("/token/revoke", web::post().to(auth::token_revoke))
+        )
+        .service(
+            api_scope("/users", json_limit)
+                .route("", web::post().to(users::users_create))
+                .route("", web::get().to(users::users_list))
+                // 需要在 /{id} 之前注册
+                .route("/with_posts", web::get().to(users::users_with_posts))
+                .route("/{id}", web::get().to(users::users_get))
+                .route("/{id}", web::patch().to(users::users_update))
+                .route("/{id}", web::delete().to(users::users_delete))
+                .route("/{id}/posts", web::post().to(posts::user_posts_create))
+                .route("/{id}/posts", web::get().to(posts::user_posts_list))
+        )
+        .service(
+            api_scope("/posts", json_limit)
+                .route("", web::get().to(posts::posts_list))
+                // 需要在 /{id} 之前注册
+                .route("/events", web::get().to(posts::posts_events))
+                .route("/{id}", web::get().to(posts::posts_get))
+                .route("/{id}", web::patch().to(posts::posts_update))
+                .route("/{id}", web::delete().to(posts::posts_delete))
+                .route("/{id}/publish", web::post().to(posts::posts_publish))
+        );
+}
+
+// 返回 ResponseWrapper 的接口，提取器错误统一转换为 AppError
+// ServiceConfig 不支持 app_data，所以配置在 scope 上
+fn api_scope(path: &str, json_limit: usize) -> Scope {
+    web::scope(path)
+        .app_data(web::JsonConfig::default().limit(json_limit).error_handler(|err, _req| AppError::from(err).into()))
+        .app_data(web::FormConfig::default().error_handler(|err, _req| AppError::from(err).into()))
+        .app_data(web::QueryConfig::default().error_handler(|err, _req| AppError::from(err).into()))
+        .app_data(web::PathConfig::default().error_handler(|err, _req| AppError::from(err).into()))
+}
+
+/// 测试共用的 SQLite 内存数据库和 `AppDeps`
+#[cfg(all(test, feature = "sqlite"))]
+pub(crate) mod testing {
+    use super::AppDeps;
+    use crate::{new_connection_pool, DbConfig, PoolConnection, Settings};
+
+    // 内存数据库每个连接相互独立，只保留一个连接
+    pub fn db_config() -> DbConfig {
+        DbConfig {
+            url: ":memory:".to_string(),
+            min_idle: Some(1),
+            max_size: 1,
+            idle_timeout: None,
+            max_lifetime: None,
+            ..DbConfig::default()
+        }
+    }
+
+    pub fn test_pool() -> PoolConnection {
+        new_connection_pool(&db_config()).unwrap()
+    }
+
+    pub fn test_deps() -> AppDeps {
+        AppDeps::new(Settings::default(), test_pool()).unwrap()
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use super::testing::{db_config, test_deps, test_pool};
+    use actix_web::{http, test, App};
+    use crate::middleware::BearerAuth;
+    use crate::{new_connection_pool, DbConfig};
+
+    // 直接在数据库中创建管理员，返回 Authorization 请求头
+    fn create_admin(deps: &AppDeps) -> String {
+        use crate::schema::user_roles;
+        use diesel::prelude::*;
+        let conn = deps.pool.get().unwrap();
+        let admin = crate::model::UserForInsert { name: "admin".to_string(), hair_color: None, password_hash: None };
+        let id = users::insert_user(&conn, &admin).unwrap().id;
+        diesel::insert_into(user_roles::table)
+            .values(&crate::model::UserRole { user_id: id, role: authz::ADMIN.to_string() })
+            .execute(&conn)
+            .unwrap();
+        let (token, _) = deps.jwt.issue(id, crate::token::TokenKind::Access).unwrap();
+        format!("Bearer {}", token)
+    }
+
+    #[actix_rt::test]
+    async fn test_app_deps_invalid_jwt() {
+        // 没有经过 Settings::validate 的配置，RS256 缺少密钥文件
+        let mut settings = Settings::default();
+        settings.jwt.algorithm = crate::settings::JwtAlgorithm::RS256;
+        assert!(matches!(AppDeps::new(settings, test_pool()), Err(SettingsError::Invalid(_))));
+    }
+
+    #[actix_rt::test]
+    async fn test_users_crud() {
+        let deps = test_deps();
+        let admin = create_admin(&deps);
+        let mut app = test::init_service(
+            App::new()
+                .wrap(BearerAuth::new(deps.jwt.clone(), deps.pool.clone()))
+                .configure(|cfg| configure_app(cfg, &deps))
+        ).await;
+
+        // 需要 users:manage 权限
+        let req = test::TestRequest::post().uri("/users")
+            .set_json(&serde_json::json!({"name": "xiaoming", "hair_color": "black"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post().uri("/users")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .set_json(&serde_json::json!({"name": "xiaoming", "hair_color": "black"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+        let req = test::TestRequest::post().uri("/users")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .set_json(&serde_json::json!({"name": "xiaoming"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+        // name_contains 中的 % 和 _ 按字面匹配
+        let req = test::TestRequest::post().uri("/users")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .set_json(&serde_json::json!({"name": "50%_off"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        for q in &["%25", "_", "0%25_o"] {
+            let req = test::TestRequest::get().uri(&format!("/users?name_contains={}", q)).to_request();
+            let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+            let names = resp["data"].as_array().unwrap().iter().map(|u| u["name"].clone()).collect::<Vec<_>>();
+            assert_eq!(names, vec!["50%_off"], "{}", q);
+        }
+
+        let req = test::TestRequest::patch().uri("/users/2")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .set_json(&serde_json::json!({"hair_color": null}))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["name"], "xiaoming");
+        assert_eq!(resp["data"]["hair_color"], serde_json::Value::Null);
+
+        let req = test::TestRequest::delete().uri("/users/2")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri("/users/2").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_authorization() {
+        let deps = test_deps();
+        let admin = create_admin(&deps);
+        let mut app = test::init_service(
+            App::new()
+                .wrap(BearerAuth::new(deps.jwt.clone(), deps.pool.clone()))
+                .configure(|cfg| configure_app(cfg, &deps))
+        ).await;
+
+        let mut tokens = Vec::new();
+        for name in &["xiaoming", "xiaohong"] {
+            let req = test::TestRequest::post().uri("/auth/register")
+                .set_json(&serde_json::json!({"name": name, "password": "12345678"}))
+                .to_request();
+            let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+            let id = resp["data"]["id"].as_i64().unwrap();
+            let (token, _) = deps.jwt.issue(id, crate::token::TokenKind::Access).unwrap();
+            tokens.push((id, format!("Bearer {}", token)));
+        }
+        let (xiaoming, xiaohong) = (&tokens[0], &tokens[1]);
+
+        // 普通用户不能访问管理员的 resource 和 scope，403 通过统一的响应格式返回
+        for uri in &["/block/user/create", "/admin/users/1/roles", "/debug/trace"] {
+            let req = test::TestRequest::get().uri(uri)
+                .header(http::header::AUTHORIZATION, xiaoming.1.as_str())
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), http::StatusCode::FORBIDDEN, "{}", uri);
+            let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+            assert_eq!(body["code"], 40300);
+        }
+        let req = test::TestRequest::get().uri("/block/user/create").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+        // 登录用户才能创建新的计数器，只有管理员可以重置
+        let req = test::TestRequest::post().uri("/counters/visits/increment").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::post().uri("/counters/visits/increment")
+            .header(http::header::AUTHORIZATION, xiaoming.1.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let req = test::TestRequest::post().uri("/counters/visits/reset")
+            .header(http::header::AUTHORIZATION, xiaoming.1.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+        let req = test::TestRequest::get().uri("/block/user/create")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        // 不能修改其他用户
+        let req = test::TestRequest::patch().uri(&format!("/users/{}", xiaohong.0))
+            .header(http::header::AUTHORIZATION, xiaoming.1.as_str())
+            .set_json(&serde_json::json!({"hair_color": "red"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+
+        // 小红发布一篇文章，小明成为 moderator 后可以删除
+        let req = test::TestRequest::post().uri(&format!("/users/{}/posts", xiaohong.0))
+            .header(http::header::AUTHORIZATION, xiaohong.1.as_str())
+            .set_json(&serde_json::json!({"title": "hello", "body": "world"}))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        let post_uri = format!("/posts/{}", resp["data"]["id"]);
+        let req = test::TestRequest::post().uri(&format!("{}/publish", post_uri))
+            .header(http::header::AUTHORIZATION, xiaohong.1.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let req = test::TestRequest::delete().uri(&post_uri)
+            .header(http::header::AUTHORIZATION, xiaoming.1.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::put().uri(&format!("/admin/users/{}/roles/moderator", xiaoming.0))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["permissions"], serde_json::json!([authz::POSTS_DELETE]));
+
+        let req = test::TestRequest::delete().uri(&post_uri)
+            .header(http::header::AUTHORIZATION, xiaoming.1.as_str())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+
+    #[actix_rt::test]
+    async fn test_require_guard() {
+        let deps = test_deps();
+        let admin = create_admin(&deps);
+        let mut app = test::init_service(
+            App::new()
+                .wrap(BearerAuth::new(deps.jwt.clone(), deps.pool.clone()))
+                .data(deps.pool.clone())
+                .service(
+                    // guard 根据外层 Authorize 加载的权限选择路由
+                    web::scope("/page")
+                        .wrap(Authorize::load())
+                        .route("", web::get().guard(authz::role(authz::ADMIN)).to(|| HttpResponse::Ok().body("admin")))
+                        .route("", web::get().to(|| HttpResponse::Ok().body("guest")))
+                )
+        ).await;
+
+        let req = test::TestRequest::get().uri("/page").to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "guest");
+        let req = test::TestRequest::get().uri("/page")
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "admin");
+    }
+
+    #[actix_rt::test]
+    async fn test_pool_exhausted() {
+        let pool = new_connection_pool(&DbConfig { connection_timeout: 1, ..db_config() }).unwrap();
+        let deps = AppDeps::new(Settings::default(), pool.clone()).unwrap();
+        let mut app = test::init_service(App::new().configure(|cfg| configure_app(cfg, &deps))).await;
+
+        // 占用唯一的连接
+        let _conn = pool.get().unwrap();
+        let req = test::TestRequest::get().uri("/users/1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(http::header::RETRY_AFTER).unwrap(), "1");
+    }
+
+    #[actix_rt::test]
+    async fn test_ws_rpc() {
+        use futures::{Sink, SinkExt, Stream, StreamExt};
+        use serde_json::{json, Value};
+
+        async fn send<S>(framed: &mut S, value: Value)
+        where
+            S: Sink<awc::ws::Message> + Unpin,
+            S::Error: std::fmt::Debug,
+        {
+            framed.send(awc::ws::Message::Text(value.to_string())).await.unwrap();
+        }
+
+        async fn recv<S>(framed: &mut S) -> Value
+        where
+            S: Stream<Item = Result<awc::ws::Frame, awc::error::WsProtocolError>> + Unpin,
+        {
+            match framed.next().await {
+                Some(Ok(awc::ws::Frame::Text(text))) => serde_json::from_slice(&text).unwrap(),
+                other => panic!("unexpected frame: {:?}", other),
+            }
+        }
+
+        let deps = test_deps();
+        let admin = create_admin(&deps);
+        let shutdown = deps.shutdown.clone();
+        let srv = test::start(move || {
+            App::new()
+                .wrap(BearerAuth::new(deps.jwt.clone(), deps.pool.clone()))
+                .configure(|cfg| configure_app(cfg, &deps))
+        });
+
+        let (_, mut anonymous) = awc::Client::new().ws(srv.url("/ws/rpc")).connect().await.unwrap();
+        assert_eq!(recv(&mut anonymous).await, json!({"event": "ready", "data": {"user_id": null}}));
+        send(&mut anonymous, json!({"id": 1, "method": "me"})).await;
+        assert_eq!(recv(&mut anonymous).await, json!({"id": 1, "code": 40100, "msg": "login required", "data": null}));
+        send(&mut anonymous, json!({"id": 2, "method": "users.get", "params": {"id": 100}})).await;
+        let resp = recv(&mut anonymous).await;
+        assert_eq!((&resp["id"], &resp["code"]), (&json!(2), &json!(40400)));
+        send(&mut anonymous, json!({"id": 3, "method": "users.list", "params": {"limit": 0}})).await;
+        assert_eq!(recv(&mut anonymous).await["code"], 40000);
+        send(&mut anonymous, json!({"id": 4, "method": "users.delete", "params": {"id": 1}})).await;
+        let resp = recv(&mut anonymous).await;
+        assert_eq!((&resp["id"], &resp["code"]), (&json!(4), &json!(40000)));
+
+        // 并发的请求通过 id 对应响应
+        send(&mut anonymous, json!({"id": 5, "method": "users.get", "params": {"id": 1}})).await;
+        send(&mut anonymous, json!({"id": 6, "method": "users.list", "params": {"limit": 1, "total": true}})).await;
+        let mut responses = [recv(&mut anonymous).await, recv(&mut anonymous).await];
+        responses.sort_by_key(|resp| resp["id"].as_u64());
+        assert_eq!(responses[0]["data"]["name"], "admin");
+        assert_eq!(responses[1]["data"][0]["name"], "admin");
+        assert_eq!(responses[1]["page"]["total"], 1);
+
+        let (_, mut logged_in) = awc::Client::new()
+            .ws(srv.url("/ws/rpc"))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .connect()
+            .await
+            .unwrap();
+        assert_eq!(recv(&mut logged_in).await, json!({"event": "ready", "data": {"user_id": 1}}));
+        send(&mut logged_in, json!({"id": 1, "method": "me"})).await;
+        let resp = recv(&mut logged_in).await;
+        assert_eq!((&resp["id"], &resp["code"], &resp["data"]["id"]), (&json!(1), &json!(0), &json!(1)));
+
+        // 服务退出时先推送 shutdown 事件，再发送 Close 帧
+        shutdown.begin();
+        assert_eq!(recv(&mut logged_in).await, json!({"event": "shutdown", "data": null}));
+        match logged_in.next().await {
+            Some(Ok(awc::ws::Frame::Close(Some(reason)))) => assert_eq!(reason.code, awc::ws::CloseCode::Away),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_post_events() {
+        use actix_web::web::Bytes;
+        use futures::{Stream, StreamExt};
+        use serde_json::{json, Value};
+
+        // 读取下一条 SSE 消息（包括注释）
+        async fn next_message<S>(body: &mut S, buf: &mut String) -> String
+        where
+            S: Stream<Item = Result<Bytes, actix_web::error::PayloadError>> + Unpin,
+        {
+            loop {
+                if let Some(i) = buf.find("\n\n") {
+                    let message = buf[..i].to_string();
+                    buf.drain(..i + 2);
+                    return message;
+                }
+                let chunk = body.next().await.unwrap().unwrap();
+                buf.push_str(std::str::from_utf8(&chunk).unwrap());
+            }
+        }
+
+        // 跳过 keep-alive，返回 (id, event, data)
+        async fn next_event<S>(body: &mut S, buf: &mut String) -> (String, String, Value)
+        where
+            S: Stream<Item = Result<Bytes, actix_web::error::PayloadError>> + Unpin,
+        {
+            loop {
+                let message = next_message(body, buf).await;
+                if message.starts_with(':') {
+                    continue;
+                }
+                let fields: Vec<(&str, &str)> = message.lines().map(|line| {
+                    let mut parts = line.splitn(2, ": ");
+                    (parts.next().unwrap(), parts.next().unwrap())
+                }).collect();
+                assert_eq!(fields.iter().map(|f| f.0).collect::<Vec<_>>(), vec!["id", "event", "data"]);
+                return (fields[0].1.to_string(), fields[1].1.to_string(), serde_json::from_str(fields[2].1).unwrap());
+            }
+        }
+
+        let mut settings = Settings::default();
+        settings.events.keep_alive = 1;
+        let deps = AppDeps::new(settings, test_pool()).unwrap();
+        let admin = create_admin(&deps);
+        let srv = test::start(move || {
+            App::new()
+                .wrap(BearerAuth::new(deps.jwt.clone(), deps.pool.clone()))
+                .configure(|cfg| configure_app(cfg, &deps))
+        });
+        let client = awc::Client::new();
+
+        let mut anonymous = client.get(srv.url("/posts/events")).send().await.unwrap();
+        assert_eq!(anonymous.status(), http::StatusCode::OK);
+        assert_eq!(anonymous.headers().get(http::header::CONTENT_TYPE).unwrap(), "text/event-stream");
+        let mut anonymous_buf = String::new();
+        let mut author = client.get(srv.url("/posts/events?kinds=created,published"))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .send()
+            .await
+            .unwrap();
+        let mut author_buf = String::new();
+        let (_, mut ws) = client.ws(srv.url("/ws/posts?user_id=1")).connect().await.unwrap();
+
+        // 空闲 keep_alive 秒后发送 keep-alive
+        assert_eq!(next_message(&mut anonymous, &mut anonymous_buf).await, ": keep-alive");
+        assert_eq!(next_message(&mut anonymous, &mut anonymous_buf).await, ": keep-alive");
+
+        let resp = client.post(srv.url("/users/1/posts"))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .send_json(&json!({"title": "hello", "body": "world"}))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let resp = client.patch(srv.url("/posts/1"))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .send_json(&json!({"title": "hi"}))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let resp = client.post(srv.url("/posts/1/publish"))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        // 未发布的文章只推送给作者
+        let (id, event, data) = next_event(&mut author, &mut author_buf).await;
+        assert_eq!((id.as_str(), event.as_str()), ("1", "created"));
+        assert_eq!(data["post"]["title"], "hello");
+        let (id, event, data) = next_event(&mut author, &mut author_buf).await;
+        assert_eq!((id.as_str(), event.as_str(), &data["post"]["title"]), ("3", "published", &json!("hi")));
+        let (id, event, _) = next_event(&mut anonymous, &mut anonymous_buf).await;
+        assert_eq!((id.as_str(), event.as_str()), ("3", "published"));
+        match ws.next().await {
+            Some(Ok(awc::ws::Frame::Text(text))) => {
+                let event: Value = serde_json::from_slice(&text).unwrap();
+                assert_eq!((&event["id"], &event["kind"], &event["post"]["published"]), (&json!(3), &json!("published"), &json!(true)));
+            }
+            other => panic!("unexpected frame: {:?}", other),
+        }
+
+        // 重连时补发 Last-Event-ID 之后的事件
+        let mut resumed = client.get(srv.url("/posts/events"))
+            .header(http::header::AUTHORIZATION, admin.as_str())
+            .header("Last-Event-ID", "1")
+            .send()
+            .await
+            .unwrap();
+        let mut resumed_buf = String::new();
+        assert_eq!(next_event(&mut resumed, &mut resumed_buf).await.0, "2");
+        assert_eq!(next_event(&mut resumed, &mut resumed_buf).await.0, "3");
+
+        let resp = client.get(srv.url("/posts/events?kinds=nope")).send().await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        let resp = client.get(srv.url("/posts/events")).header("Last-Event-ID", "x").send().await.unwrap();
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+}
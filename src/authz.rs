@@ -0,0 +1,215 @@
+//! 基于角色的授权
+//!
+//! 用户拥有若干角色（`user_roles` 表），角色拥有若干权限（`role_permissions` 表），
+//! 权限名为 `资源:操作`，`*` 表示所有权限。迁移中预置了 `admin`（所有权限）和 `moderator` 角色。
+//!
+//! 三种用法：
+//! - `middleware::Authorize` 注册在 scope 或 resource 上，不满足要求时返回 401 或 403
+//! - handler 中提取 `Principal`，检查与资源相关的权限（如只有作者或有 `posts:delete` 权限的用户可以删除文章）
+//! - `Require` 实现了 `Guard`，可以和其他 guard 组合，根据权限选择不同的路由；
+//!   guard 不匹配时路由不会被选中（最终返回 404），需要 403 时使用 `Authorize`
+//!
+//! ```ignore
+//! web::scope("/admin")
+//!     .wrap(Authorize::new(authz::role(authz::ADMIN)))
+//!     .route("/users/{id}/roles", web::get().to(admin::user_roles))
+//! ```
+
+use actix_web::dev::{Payload, RequestHead};
+use actix_web::guard::Guard;
+use actix_web::{web, FromRequest, HttpRequest};
+use diesel::prelude::*;
+use futures::future::{ready, Either, LocalBoxFuture, Ready};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::handlers::db_conn;
+use crate::handlers::viewer::current_user_id;
+use crate::{schema, AppError, DbConnection, PoolConnection};
+
+pub const ADMIN: &str = "admin";
+pub const ALL_PERMISSIONS: &str = "*";
+
+/// 创建用户、修改和删除其他用户
+pub const USERS_MANAGE: &str = "users:manage";
+/// 删除其他用户的文章
+pub const POSTS_DELETE: &str = "posts:delete";
+
+/// 当前访问者及其角色和权限，未登录时 user_id 为 None
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Principal {
+    pub user_id: Option<i64>,
+    pub roles: BTreeSet<String>,
+    pub permissions: BTreeSet<String>,
+}
+
+impl Principal {
+    pub fn anonymous() -> Self {
+        Principal::default()
+    }
+
+    pub fn load(conn: &DbConnection, user_id: i64) -> QueryResult<Self> {
+        use schema::{role_permissions, user_roles};
+        let roles = user_roles::table
+            .filter(user_roles::user_id.eq(user_id))
+            .select(user_roles::role)
+            .load::<String>(conn)?;
+        let permissions = if roles.is_empty() {
+            Vec::new()
+        } else {
+            role_permissions::table
+                .filter(role_permissions::role.eq_any(&roles))
+                .select(role_permissions::permission)
+                .load::<String>(conn)?
+        };
+        Ok(Principal {
+            user_id: Some(user_id),
+            roles: roles.into_iter().collect(),
+            permissions: permissions.into_iter().collect(),
+        })
+    }
+
+    /// 从数据库加载，未登录时不查询数据库
+    pub async fn fetch(pool: &PoolConnection, user_id: Option<i64>) -> Result<Self, AppError> {
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => return Ok(Principal::anonymous()),
+        };
+        let conn = db_conn(pool).await?;
+        Ok(web::block(move || Principal::load(&conn, user_id)).await?)
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.contains(ALL_PERMISSIONS) || self.permissions.contains(permission)
+    }
+
+    /// 未登录返回 401，不满足要求返回 403
+    pub fn ensure(&self, require: &Require) -> Result<(), AppError> {
+        if self.user_id.is_none() {
+            return Err(AppError::Unauthorized("login required".to_string()));
+        }
+        if !require.is_satisfied_by(self) {
+            return Err(AppError::Forbidden("permission denied".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 资源属于自己，或者拥有 permission
+    pub fn ensure_owner_or(&self, owner_id: i64, permission: &str) -> Result<(), AppError> {
+        if self.user_id == Some(owner_id) {
+            return Ok(());
+        }
+        self.ensure(&self::permission(permission))
+    }
+}
+
+/// 优先使用 `Authorize` 中间件已经加载的结果
+impl FromRequest for Principal {
+    type Error = AppError;
+    type Future = Either<Ready<Result<Self, AppError>>, LocalBoxFuture<'static, Result<Self, AppError>>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        if let Some(principal) = req.extensions().get::<Principal>() {
+            return Either::Left(ready(Ok(principal.clone())));
+        }
+        let user_id = current_user_id(req);
+        let pool = req.app_data::<web::Data<PoolConnection>>().cloned();
+        Either::Right(Box::pin(async move {
+            let pool = pool.ok_or_else(|| AppError::internal("PoolConnection is not registered"))?;
+            Principal::fetch(&pool, user_id).await
+        }))
+    }
+}
+
+/// 访问要求，可以使用 `or` 和 `and` 组合
+#[derive(Debug, Clone, PartialEq)]
+pub enum Require {
+    Role(String),
+    Permission(String),
+    Any(Vec<Require>),
+    All(Vec<Require>),
+}
+
+pub fn role(name: &str) -> Require {
+    Require::Role(name.to_string())
+}
+
+pub fn permission(name: &str) -> Require {
+    Require::Permission(name.to_string())
+}
+
+impl Require {
+    pub fn or(self, other: Require) -> Require {
+        match self {
+            Require::Any(mut list) => {
+                list.push(other);
+                Require::Any(list)
+            }
+            require => Require::Any(vec![require, other]),
+        }
+    }
+
+    pub fn and(self, other: Require) -> Require {
+        match self {
+            Require::All(mut list) => {
+                list.push(other);
+                Require::All(list)
+            }
+            require => Require::All(vec![require, other]),
+        }
+    }
+
+    pub fn is_satisfied_by(&self, principal: &Principal) -> bool {
+        match self {
+            Require::Role(role) => principal.has_role(role),
+            Require::Permission(permission) => principal.has_permission(permission),
+            Require::Any(list) => list.iter().any(|r| r.is_satisfied_by(principal)),
+            Require::All(list) => list.iter().all(|r| r.is_satisfied_by(principal)),
+        }
+    }
+}
+
+/// guard 在路由时执行，不能查询数据库，只检查外层 `Authorize` 中间件加载的 `Principal`
+impl Guard for Require {
+    fn check(&self, head: &RequestHead) -> bool {
+        head.extensions()
+            .get::<Principal>()
+            .is_some_and(|principal| self.is_satisfied_by(principal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(roles: &[&str], permissions: &[&str]) -> Principal {
+        Principal {
+            user_id: Some(1),
+            roles: roles.iter().map(|s| s.to_string()).collect(),
+            permissions: permissions.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_require() {
+        let admin = principal(&[ADMIN], &[ALL_PERMISSIONS]);
+        let moderator = principal(&["moderator"], &[POSTS_DELETE]);
+        let user = principal(&[], &[]);
+
+        let require = role(ADMIN).or(permission(POSTS_DELETE));
+        assert!(require.is_satisfied_by(&admin));
+        assert!(require.is_satisfied_by(&moderator));
+        assert!(!require.is_satisfied_by(&user));
+        assert!(!role("moderator").and(permission(USERS_MANAGE)).is_satisfied_by(&moderator));
+
+        assert!(user.ensure_owner_or(1, POSTS_DELETE).is_ok());
+        assert_eq!(user.ensure_owner_or(2, POSTS_DELETE).unwrap_err().code(), 40300);
+        assert!(moderator.ensure_owner_or(2, POSTS_DELETE).is_ok());
+        assert_eq!(Principal::anonymous().ensure(&role(ADMIN)).unwrap_err().code(), 40100);
+    }
+}
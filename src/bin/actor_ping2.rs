@@ -22,7 +22,8 @@ impl Message for Ping {
     type Result = Pong;
 }
 
-// 这是返回值
+// 这是返回值，字段只通过 Debug 输出
+#[allow(dead_code)]
 #[derive(Debug)]
 struct Pong(bool);
 
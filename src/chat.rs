@@ -0,0 +1,298 @@
+//! 多房间聊天（`/ws/chat`）
+//!
+//! `ChatServer` 是唯一的 actor，保存所有房间和会话的 `Recipient`，只启动一个，`Addr` 在所有 worker 之间共享，
+//! 所以不同线程上的会话可以互相广播。每个 WebSocket 连接是一个 `handlers::ws::ChatSession` actor，
+//! 在 `started` 中注册，在 `stopped` 中注销，客户端的 JSON 帧解析为 `ChatCommand` 后转发给 `ChatServer`，
+//! `ChatServer` 通过 `ChatEvent` 回复或广播。
+//!
+//! ```text
+//! -> {"cmd": "join", "room": "posts/1"}
+//! <- {"event": "joined", "room": "posts/1", "members": 2}
+//! -> {"cmd": "message", "room": "posts/1", "text": "hello"}
+//! <- {"event": "message", "room": "posts/1", "from": 3, "user_id": 1, "text": "hello"}
+//! -> {"cmd": "list"}
+//! <- {"event": "rooms", "rooms": [{"name": "posts/1", "members": 2}]}
+//! -> {"cmd": "leave", "room": "posts/1"}
+//! <- {"event": "left", "room": "posts/1"}
+//! ```
+
+use actix::{Actor, Context, Handler, Message, Recipient};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// 房间名最大长度（字节）
+pub const ROOM_MAX_LEN: usize = 64;
+/// 消息最大长度（字节）
+pub const TEXT_MAX_LEN: usize = 4096;
+
+/// 客户端发送的命令
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum ChatCommand {
+    Join { room: String },
+    Leave { room: String },
+    /// 列出所有房间
+    List,
+    /// 发送到已经加入的房间
+    Message { room: String, text: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub members: usize,
+}
+
+/// 发送给客户端的事件
+#[derive(Debug, Clone, PartialEq, Serialize, Message)]
+#[rtype(result = "()")]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum ChatEvent {
+    Joined { room: String, members: usize },
+    Left { room: String },
+    Rooms { rooms: Vec<RoomInfo> },
+    Message { room: String, from: u64, user_id: Option<i64>, text: String },
+    Error { message: String },
+}
+
+impl ChatEvent {
+    pub fn error(message: impl Into<String>) -> Self {
+        ChatEvent::Error { message: message.into() }
+    }
+}
+
+/// 注册会话，返回会话 id
+#[derive(Message)]
+#[rtype(result = "u64")]
+pub struct Connect {
+    pub session: Recipient<ChatEvent>,
+    /// 登录用户的 id，随消息一起广播
+    pub user_id: Option<i64>,
+}
+
+/// 注销会话并退出所有房间
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub id: u64,
+}
+
+/// 会话收到的命令
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Command {
+    pub id: u64,
+    pub command: ChatCommand,
+}
+
+struct Member {
+    session: Recipient<ChatEvent>,
+    user_id: Option<i64>,
+}
+
+/// 保存房间和会话，空房间自动删除
+#[derive(Default)]
+pub struct ChatServer {
+    sessions: HashMap<u64, Member>,
+    rooms: BTreeMap<String, BTreeSet<u64>>,
+    next_id: u64,
+}
+
+impl ChatServer {
+    pub fn new() -> Self {
+        ChatServer::default()
+    }
+
+    fn send(&self, id: u64, event: ChatEvent) {
+        if let Some(member) = self.sessions.get(&id) {
+            // 会话已经停止时忽略，稍后会收到 Disconnect
+            let _ = member.session.do_send(event);
+        }
+    }
+
+    fn join(&mut self, id: u64, room: String) -> ChatEvent {
+        if room.is_empty() || room.len() > ROOM_MAX_LEN {
+            return ChatEvent::error(format!("room must be 1-{} bytes", ROOM_MAX_LEN));
+        }
+        let members = self.rooms.entry(room.clone()).or_default();
+        members.insert(id);
+        ChatEvent::Joined { members: members.len(), room }
+    }
+
+    fn leave(&mut self, id: u64, room: &str) -> bool {
+        let left = match self.rooms.get_mut(room) {
+            Some(members) => members.remove(&id),
+            None => false,
+        };
+        if self.rooms.get(room).is_some_and(|members| members.is_empty()) {
+            self.rooms.remove(room);
+        }
+        left
+    }
+
+    fn broadcast(&self, id: u64, room: String, text: String) -> Result<(), ChatEvent> {
+        if text.len() > TEXT_MAX_LEN {
+            return Err(ChatEvent::error(format!("text must be at most {} bytes", TEXT_MAX_LEN)));
+        }
+        let members = match self.rooms.get(&room) {
+            Some(members) if members.contains(&id) => members,
+            _ => return Err(ChatEvent::error(format!("not in room: {}", room))),
+        };
+        let event = ChatEvent::Message {
+            user_id: self.sessions.get(&id).and_then(|m| m.user_id),
+            from: id,
+            room,
+            text,
+        };
+        for member in members {
+            self.send(*member, event.clone());
+        }
+        Ok(())
+    }
+
+    pub fn rooms(&self) -> Vec<RoomInfo> {
+        self.rooms
+            .iter()
+            .map(|(name, members)| RoomInfo { name: name.clone(), members: members.len() })
+            .collect()
+    }
+}
+
+impl Actor for ChatServer {
+    type Context = Context<Self>;
+}
+
+impl Handler<Connect> for ChatServer {
+    type Result = u64;
+
+    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> u64 {
+        self.next_id += 1;
+        self.sessions.insert(self.next_id, Member { session: msg.session, user_id: msg.user_id });
+        self.next_id
+    }
+}
+
+impl Handler<Disconnect> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
+        self.sessions.remove(&msg.id);
+        let rooms: Vec<String> = self.rooms.keys().cloned().collect();
+        for room in rooms {
+            self.leave(msg.id, &room);
+        }
+    }
+}
+
+impl Handler<Command> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Command, _: &mut Context<Self>) {
+        let reply = match msg.command {
+            ChatCommand::Join { room } => Some(self.join(msg.id, room)),
+            ChatCommand::Leave { room } => Some(if self.leave(msg.id, &room) {
+                ChatEvent::Left { room }
+            } else {
+                ChatEvent::error(format!("not in room: {}", room))
+            }),
+            ChatCommand::List => Some(ChatEvent::Rooms { rooms: self.rooms() }),
+            ChatCommand::Message { room, text } => self.broadcast(msg.id, room, text).err(),
+        };
+        if let Some(reply) = reply {
+            self.send(msg.id, reply);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::ws;
+    use crate::shutdown::Shutdown;
+    use crate::Metrics;
+    use actix_web::{web, App, HttpServer};
+    use futures::{Sink, SinkExt, Stream, StreamExt};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    async fn send<S>(framed: &mut S, value: Value)
+    where
+        S: Sink<awc::ws::Message> + Unpin,
+        S::Error: std::fmt::Debug,
+    {
+        framed.send(awc::ws::Message::Text(value.to_string())).await.unwrap();
+    }
+
+    async fn recv<S>(framed: &mut S) -> Value
+    where
+        S: Stream<Item = Result<awc::ws::Frame, awc::error::WsProtocolError>> + Unpin,
+    {
+        match framed.next().await {
+            Some(Ok(awc::ws::Frame::Text(text))) => serde_json::from_slice(&text).unwrap(),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_chat() {
+        let chat = ChatServer::new().start();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // 两个 worker，连接分配到不同的线程
+        let server = HttpServer::new(move || {
+            App::new()
+                .data(chat.clone())
+                .data(Metrics::new())
+                .data(Shutdown::new())
+                .data(crate::settings::WsSettings::default())
+                .route("/chat", web::get().to(ws::ws_chat))
+        })
+        .workers(2)
+        .disable_signals()
+        .listen(listener)
+        .unwrap()
+        .run();
+
+        let connect = || awc::Client::new().ws(format!("http://{}/chat", addr)).connect();
+        let (_, mut a) = connect().await.unwrap();
+        let (_, mut b) = connect().await.unwrap();
+
+        send(&mut a, json!({"cmd": "join", "room": "posts/1"})).await;
+        assert_eq!(recv(&mut a).await, json!({"event": "joined", "room": "posts/1", "members": 1}));
+        send(&mut b, json!({"cmd": "join", "room": "posts/1"})).await;
+        assert_eq!(recv(&mut b).await, json!({"event": "joined", "room": "posts/1", "members": 2}));
+        send(&mut b, json!({"cmd": "list"})).await;
+        assert_eq!(recv(&mut b).await, json!({"event": "rooms", "rooms": [{"name": "posts/1", "members": 2}]}));
+
+        // 房间内的所有会话（包括发送者）都会收到
+        send(&mut a, json!({"cmd": "message", "room": "posts/1", "text": "hello"})).await;
+        let message = recv(&mut a).await;
+        assert_eq!(message["text"], "hello");
+        assert_eq!(message["user_id"], Value::Null);
+        assert_eq!(recv(&mut b).await, message);
+
+        send(&mut b, json!({"cmd": "message", "room": "other", "text": "hello"})).await;
+        assert_eq!(recv(&mut b).await, json!({"event": "error", "message": "not in room: other"}));
+        a.send(awc::ws::Message::Text("hello".to_string())).await.unwrap();
+        assert!(recv(&mut a).await["message"].as_str().unwrap().starts_with("invalid command"));
+
+        send(&mut b, json!({"cmd": "leave", "room": "posts/1"})).await;
+        assert_eq!(recv(&mut b).await, json!({"event": "left", "room": "posts/1"}));
+
+        // 断开连接后退出所有房间，空房间被删除
+        a.send(awc::ws::Message::Close(None)).await.unwrap();
+        assert!(matches!(a.next().await, Some(Ok(awc::ws::Frame::Close(_)))));
+        let mut rooms = Value::Null;
+        for _ in 0..20 {
+            send(&mut b, json!({"cmd": "list"})).await;
+            rooms = recv(&mut b).await["rooms"].clone();
+            if rooms == json!([]) {
+                break;
+            }
+            actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        }
+        assert_eq!(rooms, json!([]));
+
+        server.stop(false).await;
+    }
+}
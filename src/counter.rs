@@ -0,0 +1,233 @@
+//! 进程内共享的命名计数器
+//!
+//! `HttpServer::new` 的 App 工厂在每个 worker 线程中各执行一次，通过 `data` 注册的实例每个线程一份。
+//! `Counters` 内部使用 `Arc`，clone 后所有 worker 共享同一组计数器。
+//! 计数器的值为 `AtomicI64`，读写无锁，只有第一次创建某个名字时需要获取写锁。
+//! 计数器名会作为 `/metrics` 的 label 值，最多创建 `counters.max_counters` 个。
+//!
+//! 开启持久化后，启动时从 `counters` 表加载，之后定时把变化过的计数器写回数据库。
+
+use actix_web::web;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::model::Counter;
+use crate::schema::counters;
+use crate::settings::CounterSettings;
+use crate::{AppError, DbConnection, PoolConnection};
+
+/// 计数器名最大长度，与 `counters.name` 列一致
+pub const COUNTER_NAME_MAX_LEN: usize = 64;
+
+#[derive(Default)]
+struct Entry {
+    value: AtomicI64,
+    // 上次持久化之后是否变化过
+    dirty: AtomicBool,
+}
+
+#[derive(Clone)]
+pub struct Counters {
+    entries: Arc<RwLock<HashMap<String, Arc<Entry>>>>,
+    max_counters: usize,
+}
+
+impl Counters {
+    pub fn new(settings: &CounterSettings) -> Self {
+        Counters { entries: Arc::default(), max_counters: settings.max_counters }
+    }
+
+    /// 名字只能包含字母、数字和下划线，同时也是合法的 Prometheus label 值
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= COUNTER_NAME_MAX_LEN
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<Entry>> {
+        self.entries.read().unwrap().get(name).cloned()
+    }
+
+    fn find_or_create(&self, name: &str) -> Result<Arc<Entry>, AppError> {
+        if let Some(entry) = self.find(name) {
+            return Ok(entry);
+        }
+        let mut entries = self.entries.write().unwrap();
+        if !entries.contains_key(name) && entries.len() >= self.max_counters {
+            return Err(AppError::Conflict(format!("at most {} counters can be created", self.max_counters)));
+        }
+        Ok(entries.entry(name.to_string()).or_default().clone())
+    }
+
+    fn add(entry: &Entry, by: i64) -> i64 {
+        let value = entry.value.fetch_add(by, Ordering::Relaxed).wrapping_add(by);
+        entry.dirty.store(true, Ordering::Release);
+        value
+    }
+
+    /// 不存在时返回 None
+    pub fn get(&self, name: &str) -> Option<i64> {
+        self.find(name).map(|e| e.value.load(Ordering::Relaxed))
+    }
+
+    /// 增加 by 并返回新值，不存在时返回 None
+    pub fn increment(&self, name: &str, by: i64) -> Option<i64> {
+        self.find(name).map(|entry| Counters::add(&entry, by))
+    }
+
+    /// 增加 by 并返回新值，不存在时从 0 开始，计数器数量达到上限时返回 409
+    pub fn increment_or_create(&self, name: &str, by: i64) -> Result<i64, AppError> {
+        self.find_or_create(name).map(|entry| Counters::add(&entry, by))
+    }
+
+    /// 归零并返回之前的值，不存在时返回 None
+    pub fn reset(&self, name: &str) -> Option<i64> {
+        let entry = self.find(name)?;
+        let value = entry.value.swap(0, Ordering::Relaxed);
+        entry.dirty.store(true, Ordering::Release);
+        Some(value)
+    }
+
+    /// 所有计数器的当前值，按名字排序
+    pub fn snapshot(&self) -> Vec<Counter> {
+        let mut counters = self.entries.read().unwrap()
+            .iter()
+            .map(|(name, e)| Counter { name: name.clone(), value: e.value.load(Ordering::Relaxed) })
+            .collect::<Vec<_>>();
+        counters.sort_by(|a, b| a.name.cmp(&b.name));
+        counters
+    }
+
+    /// Prometheus 文本格式
+    pub fn render_prometheus(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP app_counter Named counters, see /counters");
+        let _ = writeln!(out, "# TYPE app_counter counter");
+        for c in self.snapshot() {
+            let _ = writeln!(out, "app_counter{{name=\"{}\"}} {}", c.name, c.value);
+        }
+    }
+
+    /// 从数据库加载，覆盖内存中的同名计数器
+    pub fn load(&self, conn: &DbConnection) -> QueryResult<usize> {
+        let rows = counters::table.load::<Counter>(conn)?;
+        let mut entries = self.entries.write().unwrap();
+        for row in &rows {
+            let entry = entries.entry(row.name.clone()).or_default();
+            entry.value.store(row.value, Ordering::Relaxed);
+            entry.dirty.store(false, Ordering::Release);
+        }
+        Ok(rows.len())
+    }
+
+    /// 把上次持久化之后变化过的计数器写入数据库，返回写入的个数
+    pub fn persist(&self, conn: &DbConnection) -> QueryResult<usize> {
+        let dirty = self.entries.read().unwrap()
+            .iter()
+            .filter(|(_, e)| e.dirty.swap(false, Ordering::AcqRel))
+            .map(|(name, e)| Counter { name: name.clone(), value: e.value.load(Ordering::Relaxed) })
+            .collect::<Vec<_>>();
+        if dirty.is_empty() {
+            return Ok(0);
+        }
+
+        // 三种后端的 upsert 语法不同，先 update，不存在时再 insert
+        let result = conn.transaction(|| {
+            for c in &dirty {
+                if diesel::update(c).set(c).execute(conn)? == 0 {
+                    diesel::insert_into(counters::table).values(c).execute(conn)?;
+                }
+            }
+            Ok(dirty.len())
+        });
+        if result.is_err() {
+            // 写入失败，下次重试
+            for c in &dirty {
+                if let Some(e) = self.find(&c.name) {
+                    e.dirty.store(true, Ordering::Release);
+                }
+            }
+        }
+        result
+    }
+
+    /// 在线程池中执行一次持久化
+    pub async fn persist_with(&self, pool: &PoolConnection) -> Result<usize, AppError> {
+        let counters = self.clone();
+        let pool = pool.clone();
+        Ok(web::block(move || {
+            let conn = pool.get()?;
+            counters.persist(&conn).map_err(AppError::from)
+        }).await?)
+    }
+
+    /// 在当前 actix 运行时中启动定时持久化任务
+    pub fn spawn_persist(&self, pool: PoolConnection, interval: Duration) {
+        let counters = self.clone();
+        actix_rt::spawn(async move {
+            let mut ticker = actix_rt::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = counters.persist_with(&pool).await {
+                    log::warn!("failed to persist counters: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let counters = Counters::new(&CounterSettings { max_counters: 2, ..CounterSettings::default() });
+        assert_eq!(counters.get("a"), None);
+        assert_eq!(counters.increment("a", 1), None);
+        assert_eq!(counters.increment_or_create("a", 1).unwrap(), 1);
+        assert_eq!(counters.increment("a", 2), Some(3));
+        assert_eq!(counters.clone().increment_or_create("b", -1).unwrap(), -1);
+        assert_eq!(counters.get("b"), Some(-1));
+        // 达到上限后不能创建新的计数器，已有的不受影响
+        assert!(matches!(counters.increment_or_create("c", 1), Err(AppError::Conflict(_))));
+        assert_eq!(counters.increment_or_create("b", 1).unwrap(), 0);
+        assert_eq!(counters.increment_or_create("b", -1).unwrap(), -1);
+
+        assert_eq!(counters.reset("a"), Some(3));
+        assert_eq!(counters.get("a"), Some(0));
+        assert_eq!(counters.reset("c"), None);
+
+        let mut out = String::new();
+        counters.render_prometheus(&mut out);
+        assert!(out.contains("app_counter{name=\"a\"} 0\napp_counter{name=\"b\"} -1\n"));
+
+        assert!(Counters::is_valid_name("requests_total"));
+        assert!(!Counters::is_valid_name(""));
+        assert!(!Counters::is_valid_name("a\"b"));
+        assert!(!Counters::is_valid_name(&"a".repeat(COUNTER_NAME_MAX_LEN + 1)));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_persist_and_load() {
+        let conn = DbConnection::establish(":memory:").unwrap();
+        crate::migration::run_pending(&conn, &mut std::io::sink()).unwrap();
+
+        let counters = Counters::new(&CounterSettings::default());
+        counters.increment_or_create("a", 5).unwrap();
+        assert_eq!(counters.persist(&conn).unwrap(), 1);
+        // 没有变化时不写入
+        assert_eq!(counters.persist(&conn).unwrap(), 0);
+        counters.increment("a", 1);
+        counters.increment_or_create("b", 2).unwrap();
+        assert_eq!(counters.persist(&conn).unwrap(), 2);
+
+        let loaded = Counters::new(&CounterSettings::default());
+        assert_eq!(loaded.load(&conn).unwrap(), 2);
+        assert_eq!(loaded.snapshot(), counters.snapshot());
+    }
+}
@@ -0,0 +1,224 @@
+//! 数据库连接池配置与创建
+
+use diesel::prelude::*;
+use diesel::r2d2;
+use diesel::migration::RunMigrationsError;
+use dotenv::dotenv;
+use serde::Deserialize;
+use std::fmt;
+use std::env;
+use std::time::Duration;
+
+use crate::settings::{env_parse, env_parse_bool};
+use crate::{migration, DbConnection, PoolConnection};
+
+/// 连接池配置
+///
+/// 作为 `Settings` 的 `[database]` 部分加载，时间单位均为秒
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbConfig {
+    pub url: String,
+    pub min_idle: Option<u32>,
+    pub max_size: u32,
+    pub connection_timeout: u64,
+    pub idle_timeout: Option<u64>,
+    pub max_lifetime: Option<u64>,
+    pub test_on_checkout: bool,
+    /// 创建连接池时自动执行未执行的迁移
+    pub auto_migrate: bool,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            url: String::new(),
+            min_idle: None,
+            max_size: 15,
+            connection_timeout: 30,
+            idle_timeout: Some(600),
+            max_lifetime: Some(1800),
+            test_on_checkout: true,
+            auto_migrate: true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    database: DbConfig,
+}
+
+impl DbConfig {
+    /// 从 TOML 的 `[database]` 表中读取
+    pub fn from_toml(content: &str) -> Result<Self, DbError> {
+        toml::from_str::<ConfigFile>(content)
+            .map(|f| f.database)
+            .map_err(|e| DbError::Config(e.to_string()))
+    }
+
+    /// 使用环境变量覆盖
+    pub fn apply_env(&mut self) -> Result<(), DbError> {
+        dotenv().ok();
+
+        if let Ok(url) = env::var("DATABASE_URL") {
+            self.url = url;
+        }
+        if let Some(v) = env_parse("DATABASE_MIN_IDLE").map_err(DbError::Config)? {
+            self.min_idle = Some(v);
+        }
+        if let Some(v) = env_parse("DATABASE_MAX_SIZE").map_err(DbError::Config)? {
+            self.max_size = v;
+        }
+        if let Some(v) = env_parse("DATABASE_CONNECTION_TIMEOUT").map_err(DbError::Config)? {
+            self.connection_timeout = v;
+        }
+        if let Some(v) = env_parse("DATABASE_IDLE_TIMEOUT").map_err(DbError::Config)? {
+            self.idle_timeout = Some(v);
+        }
+        if let Some(v) = env_parse("DATABASE_MAX_LIFETIME").map_err(DbError::Config)? {
+            self.max_lifetime = Some(v);
+        }
+        if let Some(v) = env_parse_bool("DATABASE_TEST_ON_CHECKOUT").map_err(DbError::Config)? {
+            self.test_on_checkout = v;
+        }
+        if let Some(v) = env_parse_bool("DATABASE_AUTO_MIGRATE").map_err(DbError::Config)? {
+            self.auto_migrate = v;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.url.is_empty() {
+            return Err(DbError::MissingUrl);
+        }
+        if self.max_size == 0 {
+            return Err(DbError::Config("max_size must be greater than 0".to_string()));
+        }
+        if matches!(self.min_idle, Some(min) if min > self.max_size) {
+            return Err(DbError::Config("min_idle must not be greater than max_size".to_string()));
+        }
+        if self.connection_timeout == 0 {
+            return Err(DbError::Config("connection_timeout must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// 创建连接池或数据库连接时的错误
+#[derive(Debug)]
+pub enum DbError {
+    MissingUrl,
+    Config(String),
+    Pool(r2d2::PoolError),
+    Connection(ConnectionError),
+    Migration(RunMigrationsError),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::MissingUrl => write!(f, "DATABASE_URL must be set"),
+            DbError::Config(msg) => write!(f, "invalid database config: {}", msg),
+            DbError::Pool(e) => write!(f, "failed to create connection pool: {}", e),
+            DbError::Connection(e) => write!(f, "failed to connect to database: {}", e),
+            DbError::Migration(e) => write!(f, "failed to run database migrations: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<r2d2::PoolError> for DbError {
+    fn from(e: r2d2::PoolError) -> Self {
+        DbError::Pool(e)
+    }
+}
+
+impl From<ConnectionError> for DbError {
+    fn from(e: ConnectionError) -> Self {
+        DbError::Connection(e)
+    }
+}
+
+impl From<RunMigrationsError> for DbError {
+    fn from(e: RunMigrationsError) -> Self {
+        DbError::Migration(e)
+    }
+}
+
+/// 建立单个数据库连接，用于迁移等命令行操作
+pub fn establish_connection(config: &DbConfig) -> Result<DbConnection, DbError> {
+    Ok(DbConnection::establish(&config.url)?)
+}
+
+pub fn new_connection_pool(config: &DbConfig) -> Result<PoolConnection, DbError> {
+    let manager = r2d2::ConnectionManager::<DbConnection>::new(config.url.as_str());
+    let pool = r2d2::Pool::builder()
+        .min_idle(config.min_idle)
+        .max_size(config.max_size)
+        .connection_timeout(Duration::from_secs(config.connection_timeout))
+        .idle_timeout(config.idle_timeout.map(Duration::from_secs))
+        .max_lifetime(config.max_lifetime.map(Duration::from_secs))
+        .test_on_check_out(config.test_on_checkout)
+        .build(manager)?;
+
+    if config.auto_migrate {
+        let conn = pool.get()?;
+        for m in migration::run_pending(&conn, &mut std::io::sink())? {
+            log::info!("applied migration {}", m.name());
+        }
+    }
+    Ok(pool)
+}
+
+/// 同一连接上最后一次 INSERT 生成的自增 id，用于插入后读回（PostgreSQL 使用 RETURNING）
+///
+/// 不能取 id 最大的一条，并发插入时可能是其他连接插入的行
+#[cfg(not(feature = "postgres"))]
+pub fn last_insert_id(conn: &DbConnection) -> QueryResult<i64> {
+    use diesel::dsl::sql;
+    use diesel::sql_types::BigInt;
+    // LAST_INSERT_ID() 是无符号数
+    #[cfg(feature = "mysql")]
+    const LAST_INSERT_ID: &str = "CAST(LAST_INSERT_ID() AS SIGNED)";
+    #[cfg(feature = "sqlite")]
+    const LAST_INSERT_ID: &str = "last_insert_rowid()";
+
+    diesel::select(sql::<BigInt>(LAST_INSERT_ID)).get_result(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = DbConfig::from_toml(r#"
+            [database]
+            url = "test.db"
+            max_size = 4
+            idle_timeout = 60
+            auto_migrate = false
+        "#).unwrap();
+        assert_eq!(config, DbConfig {
+            url: "test.db".to_string(),
+            max_size: 4,
+            idle_timeout: Some(60),
+            auto_migrate: false,
+            ..DbConfig::default()
+        });
+        assert!(config.validate().is_ok());
+
+        assert!(DbConfig::from_toml("[database]\nmax_connections = 1").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(matches!(DbConfig::default().validate(), Err(DbError::MissingUrl)));
+
+        let config = DbConfig { url: "test.db".to_string(), min_idle: Some(20), ..DbConfig::default() };
+        assert!(matches!(config.validate(), Err(DbError::Config(_))));
+    }
+}
@@ -0,0 +1,213 @@
+//! 应用统一错误类型
+//!
+//! 所有接口的错误都渲染为 `ResponseWrapper` 格式的 JSON，`code` 为稳定的错误码：
+//!
+//! | code  | HTTP | 含义 |
+//! |-------|------|------|
+//! | 40000 | 400  | 请求参数、请求体不合法 |
+//! | 40100 | 401  | 未认证 |
+//! | 40300 | 403  | 无权限 |
+//! | 40400 | 404  | 资源不存在 |
+//! | 40900 | 409  | 资源冲突（如唯一约束） |
+//! | 41300 | 413  | 请求体过大 |
+//! | 50000 | 500  | 服务器内部错误 |
+//! | 50300 | 503  | 服务暂不可用（如连接池耗尽），带 Retry-After |
+
+use actix_web::error::{BlockingError, JsonPayloadError, PathError, QueryPayloadError, ResponseError, UrlencodedError};
+use actix_web::{http, HttpResponse};
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use std::fmt;
+
+use crate::response::ResponseWrapper;
+
+/// 连接池等待超时后建议客户端重试的间隔（秒）
+pub const POOL_RETRY_AFTER_SECS: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    PayloadTooLarge(String),
+    Internal(String),
+    ServiceUnavailable { msg: String, retry_after: u64 },
+}
+
+impl AppError {
+    /// 稳定的机器可读错误码
+    pub fn code(&self) -> i32 {
+        match self {
+            AppError::BadRequest(_) => 40000,
+            AppError::Unauthorized(_) => 40100,
+            AppError::Forbidden(_) => 40300,
+            AppError::NotFound(_) => 40400,
+            AppError::Conflict(_) => 40900,
+            AppError::PayloadTooLarge(_) => 41300,
+            AppError::Internal(_) => 50000,
+            AppError::ServiceUnavailable { .. } => 50300,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::PayloadTooLarge(msg)
+            | AppError::Internal(msg)
+            | AppError::ServiceUnavailable { msg, .. } => msg,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        AppError::NotFound("resource not found".to_string())
+    }
+
+    // 内部错误的细节只记录日志，不返回给客户端
+    pub(crate) fn internal(e: impl fmt::Display) -> Self {
+        log::error!("internal error: {}", e);
+        AppError::Internal("internal server error".to_string())
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.message())
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> http::StatusCode {
+        match self {
+            AppError::BadRequest(_) => http::StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => http::StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => http::StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => http::StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => http::StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => http::StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Internal(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::ServiceUnavailable { .. } => http::StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ResponseWrapper::<()>::from(self).into_response(None)
+    }
+}
+
+impl From<&AppError> for ResponseWrapper<()> {
+    fn from(e: &AppError) -> Self {
+        let resp = ResponseWrapper::err(e.code(), e.message()).status(e.status_code());
+        match e {
+            AppError::ServiceUnavailable { retry_after, .. } => {
+                resp.header("Retry-After", &retry_after.to_string())
+            }
+            _ => resp,
+        }
+    }
+}
+
+impl From<DieselError> for AppError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => AppError::not_found(),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                AppError::Conflict("resource already exists".to_string())
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                AppError::Conflict("resource is referenced by others".to_string())
+            }
+            e => AppError::internal(e),
+        }
+    }
+}
+
+impl From<PoolError> for AppError {
+    fn from(e: PoolError) -> Self {
+        log::warn!("failed to get db connection: {}", e);
+        AppError::ServiceUnavailable {
+            msg: "database is busy, please retry later".to_string(),
+            retry_after: POOL_RETRY_AFTER_SECS,
+        }
+    }
+}
+
+impl<E> From<BlockingError<E>> for AppError
+where
+    E: Into<AppError> + fmt::Debug,
+{
+    fn from(e: BlockingError<E>) -> Self {
+        match e {
+            BlockingError::Error(e) => e.into(),
+            BlockingError::Canceled => AppError::internal("blocking operation canceled"),
+        }
+    }
+}
+
+impl From<JsonPayloadError> for AppError {
+    fn from(e: JsonPayloadError) -> Self {
+        match e {
+            JsonPayloadError::Overflow => AppError::PayloadTooLarge(e.to_string()),
+            e => AppError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<UrlencodedError> for AppError {
+    fn from(e: UrlencodedError) -> Self {
+        match e {
+            UrlencodedError::Overflow { .. } => AppError::PayloadTooLarge(e.to_string()),
+            e => AppError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<QueryPayloadError> for AppError {
+    fn from(e: QueryPayloadError) -> Self {
+        AppError::BadRequest(e.to_string())
+    }
+}
+
+impl From<PathError> for AppError {
+    fn from(e: PathError) -> Self {
+        AppError::BadRequest(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::{Body, ResponseBody};
+
+    fn body_json(resp: &HttpResponse) -> serde_json::Value {
+        match resp.body() {
+            ResponseBody::Body(Body::Bytes(b)) => serde_json::from_slice(b).unwrap(),
+            _ => panic!("unexpected body"),
+        }
+    }
+
+    #[test]
+    fn test_diesel_error() {
+        assert_eq!(AppError::from(DieselError::NotFound).code(), 40400);
+        assert_eq!(AppError::from(BlockingError::Error(DieselError::NotFound)).code(), 40400);
+        assert_eq!(AppError::from(DieselError::RollbackTransaction).code(), 50000);
+    }
+
+    #[test]
+    fn test_error_response() {
+        let resp = AppError::Forbidden("not the owner".to_string()).error_response();
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+        let body = body_json(&resp);
+        assert_eq!(body["code"], 40300);
+        assert_eq!(body["msg"], "not the owner");
+
+        let resp = AppError::ServiceUnavailable { msg: "busy".to_string(), retry_after: 3 }.error_response();
+        assert_eq!(resp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(http::header::RETRY_AFTER).unwrap(), "3");
+    }
+}
@@ -0,0 +1,254 @@
+//! 文章变更通知
+//!
+//! 文章创建、修改、发布或删除成功后，handler 把 `Publish` 发给 `PostEvents`。`PostEvents` 只启动一个，所有 worker 共享，
+//! 它给每个事件分配递增的 id，保留最近的 `events.history_size` 个，然后推送给匹配的订阅者。
+//! 通过 `/ws/posts`（`handlers::ws::PostsSession`）或 SSE `/posts/events`（`handlers::posts::posts_events`）订阅：
+//!
+//! - 未发布的文章只推送给作者，与 `GET /posts` 的可见性一致
+//! - `user_id` 只订阅某个作者的文章，`kinds` 只订阅某些类型（逗号分隔），如 `?user_id=1&kinds=created,published`
+//! - 重连时带上最后收到的事件 id（SSE 的 `Last-Event-ID` 请求头或 `last_event_id` 参数），补发之后仍然保留的事件，
+//!   事件 id 在进程重启后从 1 开始
+//! - 每个订阅者最多缓存 `events.buffer_size` 个事件，客户端处理不过来时断开，客户端需要带上最后的事件 id 重连
+
+use actix::{Actor, Context, Handler, Message, MessageResult};
+use futures::channel::mpsc;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use crate::settings::EventsSettings;
+use crate::sse::Event;
+use crate::{model, AppError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostEventKind {
+    Created,
+    Updated,
+    Published,
+    Deleted,
+}
+
+impl PostEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostEventKind::Created => "created",
+            PostEventKind::Updated => "updated",
+            PostEventKind::Published => "published",
+            PostEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+impl FromStr for PostEventKind {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s {
+            "created" => Ok(PostEventKind::Created),
+            "updated" => Ok(PostEventKind::Updated),
+            "published" => Ok(PostEventKind::Published),
+            "deleted" => Ok(PostEventKind::Deleted),
+            _ => Err(AppError::BadRequest(format!("unknown event kind: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PostEvent {
+    pub id: u64,
+    pub kind: PostEventKind,
+    /// 变更后的文章，删除时为删除前的文章
+    pub post: model::Post,
+}
+
+impl PostEvent {
+    /// SSE 消息，`event` 为事件类型，`data` 为 JSON
+    pub fn to_event(&self) -> Event {
+        Event::json(self)
+            .expect("post event is always serializable")
+            .id(self.id.to_string())
+            .event(self.kind.as_str())
+    }
+}
+
+/// 订阅条件，为空时不过滤
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    /// 文章作者
+    pub user_id: Option<i64>,
+    pub kinds: Option<Vec<PostEventKind>>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &PostEvent) -> bool {
+        self.user_id.is_none_or(|user_id| event.post.user_id == user_id)
+            && self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&event.kind))
+    }
+}
+
+/// 订阅接口的查询参数，WebSocket 和 SSE 共用
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsQuery {
+    pub user_id: Option<i64>,
+    /// 逗号分隔的事件类型
+    pub kinds: Option<String>,
+    pub last_event_id: Option<u64>,
+}
+
+impl EventsQuery {
+    pub fn filter(&self) -> Result<EventFilter, AppError> {
+        let kinds = match &self.kinds {
+            Some(kinds) => Some(kinds.split(',').map(|kind| kind.trim().parse()).collect::<Result<Vec<_>, _>>()?),
+            None => None,
+        };
+        Ok(EventFilter { user_id: self.user_id, kinds })
+    }
+}
+
+/// handler 在变更成功后发送
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Publish {
+    pub kind: PostEventKind,
+    pub post: model::Post,
+}
+
+/// 订阅，返回接收事件的 channel，channel 关闭表示被断开
+#[derive(Message)]
+#[rtype(result = "mpsc::Receiver<PostEvent>")]
+pub struct Subscribe {
+    /// 当前登录的用户，可以收到自己未发布的文章
+    pub viewer: Option<i64>,
+    pub filter: EventFilter,
+    /// 先补发这个 id 之后的事件
+    pub last_event_id: Option<u64>,
+}
+
+struct Subscriber {
+    sender: mpsc::Sender<PostEvent>,
+    viewer: Option<i64>,
+    filter: EventFilter,
+}
+
+impl Subscriber {
+    fn wants(&self, event: &PostEvent) -> bool {
+        wants(self.viewer, &self.filter, event)
+    }
+}
+
+fn wants(viewer: Option<i64>, filter: &EventFilter, event: &PostEvent) -> bool {
+    (event.post.published || viewer == Some(event.post.user_id)) && filter.matches(event)
+}
+
+pub struct PostEvents {
+    settings: EventsSettings,
+    history: VecDeque<PostEvent>,
+    subscribers: Vec<Subscriber>,
+    next_id: u64,
+}
+
+impl PostEvents {
+    pub fn new(settings: &EventsSettings) -> Self {
+        PostEvents { settings: settings.clone(), history: VecDeque::new(), subscribers: Vec::new(), next_id: 1 }
+    }
+}
+
+impl Actor for PostEvents {
+    type Context = Context<Self>;
+}
+
+impl Handler<Publish> for PostEvents {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _: &mut Context<Self>) {
+        let event = PostEvent { id: self.next_id, kind: msg.kind, post: msg.post };
+        self.next_id += 1;
+        // 缓冲已满或者客户端已经断开时删除订阅者，sender 被 drop 后客户端的 channel 关闭
+        self.subscribers.retain_mut(|s| !s.wants(&event) || s.sender.try_send(event.clone()).is_ok());
+        if self.settings.history_size > 0 {
+            if self.history.len() == self.settings.history_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(event);
+        }
+    }
+}
+
+impl Handler<Subscribe> for PostEvents {
+    type Result = MessageResult<Subscribe>;
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Context<Self>) -> Self::Result {
+        let missed: Vec<PostEvent> = match msg.last_event_id {
+            Some(last_event_id) => self.history.iter()
+                .filter(|event| event.id > last_event_id && wants(msg.viewer, &msg.filter, event))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        // channel 的容量是 buffer + sender 数，补发的事件不占用缓冲
+        let (mut sender, receiver) = mpsc::channel(self.settings.buffer_size - 1 + missed.len());
+        for event in missed {
+            sender.try_send(event).expect("channel has room for missed events");
+        }
+        self.subscribers.retain(|s| !s.sender.is_closed());
+        self.subscribers.push(Subscriber { sender, viewer: msg.viewer, filter: msg.filter });
+        MessageResult(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Addr;
+
+    fn post(id: i64, user_id: i64, published: bool) -> model::Post {
+        model::Post { id, user_id, title: "title".to_string(), body: "body".to_string(), published }
+    }
+
+    async fn subscribe(events: &Addr<PostEvents>, viewer: Option<i64>, filter: EventFilter, last_event_id: Option<u64>) -> mpsc::Receiver<PostEvent> {
+        events.send(Subscribe { viewer, filter, last_event_id }).await.unwrap()
+    }
+
+    // 已经收到的事件，以及 channel 是否已经关闭
+    fn received(receiver: &mut mpsc::Receiver<PostEvent>) -> (Vec<(u64, PostEventKind)>, bool) {
+        let mut events = Vec::new();
+        loop {
+            match receiver.try_next() {
+                Ok(Some(event)) => events.push((event.id, event.kind)),
+                Ok(None) => return (events, true),
+                Err(_) => return (events, false),
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_post_events() {
+        let events = PostEvents::new(&EventsSettings { history_size: 3, buffer_size: 2, keep_alive: 15 }).start();
+        let mut anonymous = subscribe(&events, None, EventFilter::default(), None).await;
+        let mut author = subscribe(&events, Some(1), EventFilter::default(), None).await;
+        let query = EventsQuery { user_id: Some(2), kinds: Some("created, published".to_string()), last_event_id: None };
+        let mut filtered = subscribe(&events, None, query.filter().unwrap(), None).await;
+        assert!(EventsQuery { kinds: Some("created,nope".to_string()), ..EventsQuery::default() }.filter().is_err());
+
+        events.do_send(Publish { kind: PostEventKind::Created, post: post(1, 1, false) });
+        events.do_send(Publish { kind: PostEventKind::Published, post: post(1, 1, true) });
+        events.do_send(Publish { kind: PostEventKind::Created, post: post(2, 2, true) });
+        events.do_send(Publish { kind: PostEventKind::Updated, post: post(2, 2, true) });
+        // 等待前面的消息处理完
+        let mut resumed = subscribe(&events, None, EventFilter::default(), Some(2)).await;
+
+        // 未发布的文章只推送给作者
+        assert_eq!(received(&mut filtered), (vec![(3, PostEventKind::Created)], false));
+        assert_eq!(received(&mut resumed), (vec![(3, PostEventKind::Created), (4, PostEventKind::Updated)], false));
+        // 缓冲已满，之后的事件丢弃并断开
+        assert_eq!(received(&mut author), (vec![(1, PostEventKind::Created), (2, PostEventKind::Published)], true));
+        assert_eq!(received(&mut anonymous), (vec![(2, PostEventKind::Published), (3, PostEventKind::Created)], true));
+
+        // 只保留最近 3 个事件
+        let mut resumed = subscribe(&events, Some(1), EventFilter::default(), Some(0)).await;
+        assert_eq!(received(&mut resumed).0.iter().map(|e| e.0).collect::<Vec<_>>(), vec![2, 3, 4]);
+        let mut tail = subscribe(&events, None, EventFilter::default(), Some(4)).await;
+        assert_eq!(received(&mut tail), (vec![], false));
+    }
+}
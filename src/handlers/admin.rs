@@ -0,0 +1,75 @@
+//! /admin：管理用户的角色，只有管理员可以访问（见 `crate::app`）
+
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use super::db_conn;
+use crate::authz::Principal;
+use crate::{model, schema, AppError, PoolConnection, ResponseWrapper};
+
+/// 角色名最大长度，与数据库中的列一致
+pub const ROLE_MAX_LEN: usize = 64;
+
+// curl -i -b cookies.txt http://localhost:8088/admin/users/1/roles
+pub async fn user_roles(pool: web::Data<PoolConnection>, id: web::Path<i64>) -> Result<ResponseWrapper<Principal>, AppError> {
+    let conn = db_conn(&pool).await?;
+
+    let principal = web::block(move || {
+        use schema::users;
+        let user = users::table
+            .find(id.into_inner())
+            .first::<model::User>(&conn)?;
+        Principal::load(&conn, user.id)
+    }).await?;
+
+    Ok(ResponseWrapper::ok(principal))
+}
+
+// 已经拥有该角色时同样返回 200
+// curl -i -b cookies.txt -X PUT http://localhost:8088/admin/users/1/roles/moderator
+pub async fn user_roles_add(pool: web::Data<PoolConnection>, path: web::Path<(i64, String)>) -> Result<ResponseWrapper<Principal>, AppError> {
+    let (user_id, role) = path.into_inner();
+    if role.is_empty() || role.len() > ROLE_MAX_LEN {
+        return Err(AppError::BadRequest(format!("role must be 1-{} bytes", ROLE_MAX_LEN)));
+    }
+    let conn = db_conn(&pool).await?;
+
+    let principal = web::block(move || {
+        use schema::{user_roles, users};
+        let user = users::table
+            .find(user_id)
+            .first::<model::User>(&conn)?;
+        let inserted = diesel::insert_into(user_roles::table)
+            .values(&model::UserRole { user_id: user.id, role })
+            .execute(&conn);
+        match inserted {
+            Ok(_) | Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+            Err(e) => return Err(e),
+        }
+        Principal::load(&conn, user.id)
+    }).await?;
+
+    Ok(ResponseWrapper::ok(principal))
+}
+
+// curl -i -b cookies.txt -X DELETE http://localhost:8088/admin/users/1/roles/moderator
+pub async fn user_roles_remove(pool: web::Data<PoolConnection>, path: web::Path<(i64, String)>) -> Result<ResponseWrapper<()>, AppError> {
+    let (user_id, role) = path.into_inner();
+    let conn = db_conn(&pool).await?;
+
+    web::block(move || {
+        use schema::user_roles;
+        let deleted = diesel::delete(
+            user_roles::table
+                .filter(user_roles::user_id.eq(user_id))
+                .filter(user_roles::role.eq(role))
+        ).execute(&conn)?;
+        match deleted {
+            0 => Err(DieselError::NotFound),
+            _ => Ok(()),
+        }
+    }).await?;
+
+    Ok(ResponseWrapper::no_content())
+}
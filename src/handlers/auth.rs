@@ -0,0 +1,303 @@
+//! /auth：注册、登录和退出
+//!
+//! 浏览器使用 `/auth/login`，登录成功后用户 id 保存在服务端 session 中（见 `crate::session`）；
+//! 其他客户端使用 `/auth/token` 获取 Bearer token，见 `crate::token`。
+//! 两种方式登录后都通过 `viewer::CurrentUser` 读取当前用户。
+
+use actix_session::Session;
+use actix_web::{web, HttpRequest};
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use super::db_conn;
+use super::users::insert_user;
+use super::viewer::{CurrentUser, USER_ID_KEY};
+use crate::token::{self, BearerIdentity, Jwt, TokenKind, TokenPair};
+use crate::{model, password, schema, AppError, PoolConnection, ResponseWrapper};
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterForm {
+    pub name: String,
+    pub password: String,
+    pub hair_color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshForm {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeForm {
+    /// access token 或 refresh token
+    pub token: String,
+}
+
+// curl -i -H 'Content-Type: application/json' -d '{"name": "xiaoming", "password": "12345678"}' -X POST http://localhost:8088/auth/register
+pub async fn register(pool: web::Data<PoolConnection>, form: web::Json<RegisterForm>) -> Result<ResponseWrapper<model::User>, AppError> {
+    let form = form.into_inner();
+    password::validate(&form.password)?;
+    let conn = db_conn(&pool).await?;
+
+    let user = web::block(move || {
+        let user = model::UserForInsert {
+            name: form.name,
+            hair_color: form.hair_color,
+            password_hash: Some(password::hash(&form.password)?),
+        };
+        insert_user(&conn, &user).map_err(AppError::from)
+    }).await?;
+
+    Ok(ResponseWrapper::created(user))
+}
+
+// 用户不存在和密码错误返回相同的错误
+async fn check_password(pool: &PoolConnection, form: LoginForm) -> Result<model::User, AppError> {
+    let conn = db_conn(pool).await?;
+
+    let user = web::block(move || {
+        use schema::users;
+        let user = users::table
+            .filter(users::name.eq(&form.name))
+            .first::<model::User>(&conn)
+            .optional()?;
+        // 用户不存在时也验证一次，耗时与密码错误相同
+        let hash = user.as_ref().and_then(|u| u.password_hash.as_deref());
+        let verified = password::verify(hash.unwrap_or(password::DUMMY_HASH), &form.password) && hash.is_some();
+        Ok::<_, AppError>(user.filter(|_| verified))
+    }).await?;
+    user.ok_or_else(|| AppError::Unauthorized("invalid name or password".to_string()))
+}
+
+// curl -i -c cookies.txt -H 'Content-Type: application/json' -d '{"name": "xiaoming", "password": "12345678"}' -X POST http://localhost:8088/auth/login
+pub async fn login(
+    pool: web::Data<PoolConnection>,
+    session: Session,
+    form: web::Json<LoginForm>,
+) -> Result<ResponseWrapper<model::User>, AppError> {
+    let user = check_password(&pool, form.into_inner()).await?;
+
+    // 登录后更换 session id，避免 session 固定攻击
+    session.renew();
+    session.set(USER_ID_KEY, user.id).map_err(AppError::internal)?;
+    Ok(ResponseWrapper::ok(user))
+}
+
+// 删除服务端 session 和 cookie，使用 Bearer token 时同时撤销这个 access token
+// curl -i -b cookies.txt -c cookies.txt -X POST http://localhost:8088/auth/logout
+pub async fn logout(req: HttpRequest, pool: web::Data<PoolConnection>, session: Session) -> Result<ResponseWrapper<()>, AppError> {
+    session.purge();
+    let identity = req.extensions().get::<BearerIdentity>().cloned();
+    if let Some(identity) = identity {
+        let conn = db_conn(&pool).await?;
+        web::block(move || token::revoke(&conn, &identity.claims)).await?;
+    }
+    Ok(ResponseWrapper::no_content())
+}
+
+// curl -i -b cookies.txt http://localhost:8088/auth/me
+// curl -i -H 'Authorization: Bearer <access_token>' http://localhost:8088/auth/me
+pub async fn me(current: CurrentUser) -> ResponseWrapper<model::User> {
+    ResponseWrapper::ok(current.0)
+}
+
+// curl -i -H 'Content-Type: application/json' -d '{"name": "xiaoming", "password": "12345678"}' -X POST http://localhost:8088/auth/token
+pub async fn token(
+    pool: web::Data<PoolConnection>,
+    jwt: web::Data<Jwt>,
+    form: web::Json<LoginForm>,
+) -> Result<ResponseWrapper<TokenPair>, AppError> {
+    let user = check_password(&pool, form.into_inner()).await?;
+    Ok(ResponseWrapper::ok(jwt.issue_pair(user.id)?))
+}
+
+// refresh token 只能使用一次，换取新的一对 token 后旧的被撤销
+// curl -i -H 'Content-Type: application/json' -d '{"refresh_token": "<refresh_token>"}' -X POST http://localhost:8088/auth/token/refresh
+pub async fn token_refresh(
+    pool: web::Data<PoolConnection>,
+    jwt: web::Data<Jwt>,
+    form: web::Json<RefreshForm>,
+) -> Result<ResponseWrapper<TokenPair>, AppError> {
+    let claims = jwt.decode(&form.refresh_token, TokenKind::Refresh)?;
+    let user_id = claims.user_id()?;
+    let conn = db_conn(&pool).await?;
+
+    web::block(move || {
+        use schema::users;
+        // 并发使用同一个 refresh token 时只有一个请求能撤销成功
+        if !token::revoke(&conn, &claims)? {
+            return Err(AppError::Unauthorized("token revoked".to_string()));
+        }
+        users::table
+            .find(user_id)
+            .select(users::id)
+            .first::<i64>(&conn)
+            .optional()?
+            .ok_or_else(|| AppError::Unauthorized("user not found".to_string()))
+    }).await?;
+
+    Ok(ResponseWrapper::ok(jwt.issue_pair(user_id)?))
+}
+
+// 撤销 access token 或 refresh token，已经撤销过的 token 同样返回 204
+// curl -i -H 'Content-Type: application/json' -d '{"token": "<refresh_token>"}' -X POST http://localhost:8088/auth/token/revoke
+pub async fn token_revoke(
+    pool: web::Data<PoolConnection>,
+    jwt: web::Data<Jwt>,
+    form: web::Json<RevokeForm>,
+) -> Result<ResponseWrapper<()>, AppError> {
+    let claims = jwt.decode(&form.token, TokenKind::Access)
+        .or_else(|_| jwt.decode(&form.token, TokenKind::Refresh))?;
+    let conn = db_conn(&pool).await?;
+    web::block(move || token::revoke(&conn, &claims)).await?;
+    Ok(ResponseWrapper::no_content())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::middleware::{BearerAuth, ServerSession};
+    use crate::app::testing::test_deps;
+    use crate::configure_app;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_auth() {
+        let deps = test_deps();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(ServerSession::new(deps.sessions.clone()))
+                .configure(|cfg| configure_app(cfg, &deps))
+        ).await;
+
+        let req = test::TestRequest::post().uri("/auth/register")
+            .set_json(&serde_json::json!({"name": "xiaoming", "password": "short"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post().uri("/auth/register")
+            .set_json(&serde_json::json!({"name": "xiaoming", "password": "12345678"}))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["name"], "xiaoming");
+        assert!(resp["data"].get("password_hash").is_none(), "{}", resp);
+
+        let req = test::TestRequest::post().uri("/auth/login")
+            .set_json(&serde_json::json!({"name": "xiaoming", "password": "87654321"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get().uri("/auth/me").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post().uri("/auth/login")
+            .set_json(&serde_json::json!({"name": "xiaoming", "password": "12345678"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+
+        let req = test::TestRequest::get().uri("/auth/me").cookie(cookie.clone()).to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["name"], "xiaoming");
+
+        // 只有作者本人可以发布文章
+        let req = test::TestRequest::post().uri("/users/1/posts")
+            .set_json(&serde_json::json!({"title": "hello", "body": "world"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::post().uri("/users/1/posts")
+            .cookie(cookie.clone())
+            .set_json(&serde_json::json!({"title": "hello", "body": "world"}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let req = test::TestRequest::post().uri("/auth/logout").cookie(cookie.clone()).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let removal = resp.response().cookies().next().unwrap().into_owned();
+        assert_eq!(removal.path(), Some("/"));
+        assert_eq!(removal.value(), "");
+
+        // 服务端数据已经删除，退出前的 cookie 也不能再使用
+        let req = test::TestRequest::get().uri("/auth/me").cookie(cookie).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_bearer_token() {
+        let deps = test_deps();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(BearerAuth::new(deps.jwt.clone(), deps.pool.clone()))
+                .configure(|cfg| configure_app(cfg, &deps))
+        ).await;
+
+        let req = test::TestRequest::post().uri("/auth/register")
+            .set_json(&serde_json::json!({"name": "xiaoming", "password": "12345678"}))
+            .to_request();
+        test::call_service(&mut app, req).await;
+
+        let req = test::TestRequest::post().uri("/auth/token")
+            .set_json(&serde_json::json!({"name": "xiaoming", "password": "12345678"}))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["token_type"], "Bearer");
+        let access = resp["data"]["access_token"].as_str().unwrap().to_string();
+        let refresh = resp["data"]["refresh_token"].as_str().unwrap().to_string();
+        let bearer = |token: &str| format!("Bearer {}", token);
+
+        let req = test::TestRequest::get().uri("/auth/me")
+            .header(header::AUTHORIZATION, bearer(&access))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["name"], "xiaoming");
+
+        // refresh token 不能访问接口，无效的 token 直接返回 401
+        for token in &[refresh.as_str(), "not-a-token"] {
+            let req = test::TestRequest::get().uri("/posts")
+                .header(header::AUTHORIZATION, bearer(token))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let req = test::TestRequest::post().uri("/auth/token/refresh")
+            .set_json(&serde_json::json!({"refresh_token": refresh}))
+            .to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert!(resp["data"]["access_token"].is_string(), "{}", resp);
+        // refresh token 只能使用一次
+        let req = test::TestRequest::post().uri("/auth/token/refresh")
+            .set_json(&serde_json::json!({"refresh_token": refresh}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post().uri("/auth/token/revoke")
+            .set_json(&serde_json::json!({"token": access}))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get().uri("/auth/me")
+            .header(header::AUTHORIZATION, bearer(&access))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["code"], 40100);
+        assert_eq!(body["msg"], "token revoked");
+    }
+}
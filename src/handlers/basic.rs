@@ -0,0 +1,90 @@
+//! 入门示例：路由、应用状态与配置函数
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::Settings;
+
+// curl http://localhost:8088/
+// curl http://localhost:8088/app/index.html
+pub async fn index() -> impl Responder {
+    HttpResponse::Ok().body("Hello world!")
+}
+
+// curl http://localhost:8088/again
+pub async fn index2() -> impl Responder {
+    HttpResponse::Ok().body("Hello world again!")
+}
+
+// curl http://localhost:8088/hello
+// curl http://localhost:8088/app/hello
+// 使用宏解析
+#[get("/hello")]
+pub async fn index3() -> impl Responder {
+    HttpResponse::Ok().body("Hey there!")
+}
+
+// curl http://localhost:8088/app_state
+pub async fn app_state(settings: web::Data<Settings>) -> impl Responder {
+    HttpResponse::Ok().body(&settings.app.name)
+}
+
+// this function could be located in different module
+// curl http://localhost:8088/app3/test
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/test")
+            .route(web::get().to(|| HttpResponse::Ok().body("test")))
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
+    );
+}
+
+// this function could be located in different module
+// curl http://localhost:8088/app2
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/app2")
+            .route(web::get().to(|| HttpResponse::Ok().body("app2")))
+            .route(web::head().to(HttpResponse::MethodNotAllowed)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http, test, App};
+
+    // 单元测试
+    #[actix_rt::test]
+    async fn test_index_ok(){
+        // 构建测试的TestRequest
+        let req = test::TestRequest::get()
+            .header("content-type", "text/plain")
+            // .get()
+            .to_http_request();
+
+        println!("{:?}", req);
+
+        // 执行测试函数
+        let resp = index().await.respond_to(&req).await.ok().unwrap();
+
+        // 断言
+        assert_eq!(resp.status(), http::StatusCode::OK);
+    }
+
+    // 继承测试
+    #[actix_rt::test]
+    async fn test_index_get() {
+        let mut app = test::init_service(App::new().route("/", web::get().to(index))).await;
+        let req = test::TestRequest::with_header("content-type", "text/plain").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_index_post() {
+        let mut app = test::init_service(App::new().route("/", web::get().to(index))).await;
+        let req = test::TestRequest::post().uri("/").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+}
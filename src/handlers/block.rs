@@ -0,0 +1,36 @@
+//! /block：在线程池中执行阻塞的数据库操作
+
+use actix_web::web;
+use diesel::prelude::*;
+
+use super::db_conn;
+use crate::authz;
+use crate::middleware::Authorize;
+use crate::{model, schema, AppError, PoolConnection};
+
+// 只有管理员可以访问
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/user/create")
+            .wrap(Authorize::new(authz::role(authz::ADMIN)))
+            .route(web::get().to(create_user)),
+    );
+}
+
+// curl -b cookies.txt http://localhost:8088/block/user/create
+pub async fn create_user(pool: web::Data<PoolConnection>) -> Result<String, AppError> {
+    let conn = db_conn(&pool).await?;
+
+    web::block(move ||  {
+        use schema::users;
+        let user = model::UserForInsert {
+            name: "name".to_string(),
+            hair_color: Some("blank".to_string()),
+            password_hash: None,
+        };
+        diesel::insert_into(users::table)
+            .values(&user)
+            .execute(&conn)
+    }).await?;
+    Ok(String::from("create_success"))
+}
@@ -0,0 +1,123 @@
+//! /counters：进程内共享的命名计数器
+
+use actix_web::web;
+use serde::Deserialize;
+
+use super::viewer::Viewer;
+use crate::counter::Counters;
+use crate::model::Counter;
+use crate::{AppError, ResponseWrapper};
+
+/// 每次最多增加的值，计数器只能增加，归零使用 reset
+pub const INCREMENT_MAX: i64 = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct IncrementQuery {
+    #[serde(default = "default_by")]
+    by: i64,
+}
+
+fn default_by() -> i64 {
+    1
+}
+
+fn check_name(name: &str) -> Result<(), AppError> {
+    if Counters::is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest("counter name must be 1-64 letters, digits or underscores".to_string()))
+    }
+}
+
+// curl -i http://localhost:8088/counters
+pub async fn counters_list(counters: web::Data<Counters>) -> ResponseWrapper<Vec<Counter>> {
+    ResponseWrapper::ok(counters.snapshot())
+}
+
+// curl -i http://localhost:8088/counters/visits
+pub async fn counters_get(counters: web::Data<Counters>, name: web::Path<String>) -> Result<ResponseWrapper<Counter>, AppError> {
+    let name = name.into_inner();
+    check_name(&name)?;
+    let value = counters.get(&name).ok_or_else(AppError::not_found)?;
+    Ok(ResponseWrapper::ok(Counter { name, value }))
+}
+
+// 只有登录用户可以创建新的计数器，数量上限见 `counters.max_counters`
+// curl -i -X POST http://localhost:8088/counters/visits/increment
+// curl -i -b cookies.txt -X POST 'http://localhost:8088/counters/visits/increment?by=10'
+pub async fn counters_increment(
+    counters: web::Data<Counters>,
+    viewer: Viewer,
+    name: web::Path<String>,
+    q: web::Query<IncrementQuery>,
+) -> Result<ResponseWrapper<Counter>, AppError> {
+    let name = name.into_inner();
+    check_name(&name)?;
+    if !(1..=INCREMENT_MAX).contains(&q.by) {
+        return Err(AppError::BadRequest(format!("by must be 1-{}", INCREMENT_MAX)));
+    }
+    let value = match viewer.0 {
+        Some(_) => counters.increment_or_create(&name, q.by)?,
+        None => counters.increment(&name, q.by)
+            .ok_or_else(|| AppError::Unauthorized("login required to create a counter".to_string()))?,
+    };
+    Ok(ResponseWrapper::ok(Counter { name, value }))
+}
+
+// 只有管理员可以重置（见 `crate::app`）
+// curl -i -b cookies.txt -X POST http://localhost:8088/counters/visits/reset
+pub async fn counters_reset(counters: web::Data<Counters>, name: web::Path<String>) -> Result<ResponseWrapper<Counter>, AppError> {
+    let name = name.into_inner();
+    check_name(&name)?;
+    counters.reset(&name).ok_or_else(AppError::not_found)?;
+    Ok(ResponseWrapper::ok(Counter { name, value: 0 }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::CounterSettings;
+    use actix_web::{http, test, App};
+
+    #[actix_rt::test]
+    async fn test_counters() {
+        let counters = Counters::new(&CounterSettings::default());
+        counters.increment_or_create("visits", 0).unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .data(counters)
+                .route("/counters/{name}", web::get().to(counters_get))
+                .route("/counters/{name}/increment", web::post().to(counters_increment))
+                .route("/counters/{name}/reset", web::post().to(counters_reset))
+        ).await;
+
+        let req = test::TestRequest::get().uri("/counters/other").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+        // 未登录时不能创建新的计数器
+        let req = test::TestRequest::post().uri("/counters/other/increment").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post().uri("/counters/visits/increment?by=2").to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["value"], 2);
+
+        let req = test::TestRequest::post().uri("/counters/visits/reset").to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["value"], 0);
+
+        for by in &["0", "-1", "-9223372036854775808", "1001"] {
+            let req = test::TestRequest::post().uri(&format!("/counters/visits/increment?by={}", by)).to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST, "{}", by);
+        }
+        let req = test::TestRequest::get().uri("/counters/visits").to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["value"], 0);
+
+        let req = test::TestRequest::post().uri("/counters/bad-name/increment").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+}
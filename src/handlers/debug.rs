@@ -0,0 +1,52 @@
+//! /debug：请求追踪（见 `crate::trace`），只有管理员可以访问
+
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+
+use crate::trace::{TraceRecord, Tracer};
+use crate::ResponseWrapper;
+
+#[derive(Deserialize, Debug)]
+pub struct RecentQuery {
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TraceStatus {
+    pub enabled: bool,
+    /// 缓冲区中的记录数，只在响应中返回
+    #[serde(default, skip_deserializing)]
+    pub buffered: usize,
+}
+
+fn status(tracer: &Tracer) -> TraceStatus {
+    TraceStatus { enabled: tracer.is_enabled(), buffered: tracer.buffered() }
+}
+
+// curl -i -b cookies.txt 'http://localhost:8088/debug/requests?limit=10'
+pub async fn debug_requests(tracer: web::Data<Tracer>, q: web::Query<RecentQuery>) -> ResponseWrapper<Vec<TraceRecord>> {
+    ResponseWrapper::ok(tracer.recent(q.limit))
+}
+
+// curl -i -b cookies.txt -X DELETE http://localhost:8088/debug/requests
+pub async fn debug_requests_clear(tracer: web::Data<Tracer>) -> ResponseWrapper<()> {
+    tracer.clear();
+    ResponseWrapper::no_content()
+}
+
+// curl -i -b cookies.txt http://localhost:8088/debug/trace
+pub async fn debug_trace(tracer: web::Data<Tracer>) -> ResponseWrapper<TraceStatus> {
+    ResponseWrapper::ok(status(&tracer))
+}
+
+// curl -i -b cookies.txt -X PUT -H 'Content-Type: application/json' -d '{"enabled": true}' http://localhost:8088/debug/trace
+pub async fn debug_trace_update(tracer: web::Data<Tracer>, body: web::Json<TraceStatus>) -> ResponseWrapper<TraceStatus> {
+    tracer.set_enabled(body.enabled);
+    log::info!("request tracing {}", if body.enabled { "enabled" } else { "disabled" });
+    ResponseWrapper::ok(status(&tracer))
+}
@@ -0,0 +1,56 @@
+//! /error：自定义错误
+
+use actix_http::ResponseBuilder;
+use actix_web::{error, http, HttpResponse, Result};
+use failure::Fail;
+
+#[derive(Fail, Debug)]
+#[fail(display = "my error")]
+pub struct MyError {
+    name: &'static str,
+}
+
+impl error::ResponseError for MyError {}
+
+// curl -i http://localhost:8088/error/custom
+pub async fn error_custom() -> Result<&'static str, MyError> {
+    Err(MyError { name: "test" })
+}
+
+#[derive(Fail, Debug)]
+#[allow(dead_code)]
+pub enum MyErrorEnum {
+    #[fail(display = "internal error")]
+    InternalError,
+    #[fail(display = "bad request")]
+    BadClientData,
+    #[fail(display = "timeout")]
+    Timeout,
+}
+
+impl error::ResponseError for MyErrorEnum {
+    fn error_response(&self) -> HttpResponse {
+        ResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(self.to_string())
+    }
+
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            MyErrorEnum::InternalError => http::StatusCode::INTERNAL_SERVER_ERROR,
+            MyErrorEnum::BadClientData => http::StatusCode::BAD_REQUEST,
+            MyErrorEnum::Timeout => http::StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+// curl -i http://localhost:8088/error/enum
+pub async fn error_enum() -> Result<&'static str, MyErrorEnum> {
+    Err(MyErrorEnum::BadClientData)
+}
+
+// curl -i http://localhost:8088/error/helper
+pub async fn error_helper() -> Result<&'static str> {
+    let result: Result<&'static str, MyError> = Err(MyError { name: "test error" });
+
+    result.map_err(|e| error::ErrorBadRequest(e.name))
+}
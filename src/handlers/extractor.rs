@@ -0,0 +1,71 @@
+//! /extractor：各种提取器
+
+use actix_web::{web, HttpRequest};
+use serde::Deserialize;
+
+// 提取器 extractors
+// 字段只通过 Debug 输出
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct QueryInfo {
+    username: String,
+}
+
+// curl http://localhost:8088/extractor/multiple/p1/p2?username=xiaoming
+pub async fn extractor_multiple(p: web::Path<(String, String)>, q: web::Query<QueryInfo>) -> String {
+    format!("p={:?}, q={:?}", p, q)
+}
+
+// 字段只通过 Debug 输出
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct PathInfo {
+    user_id: u32,
+    friend: String,
+}
+
+// curl http://localhost:8088/extractor/path/123/friend_name
+pub async fn extractor_path(p: web::Path<PathInfo>) -> String {
+    format!("path-param={:?}", p)
+}
+
+// curl http://localhost:8088/extractor/manual_path/123/friend_name
+pub async fn extractor_manual_path(req: HttpRequest) -> String {
+    let friend: String =
+        req.match_info().get("friend").unwrap().parse().unwrap();
+    let user_id: i32 = req.match_info().query("user_id").parse().unwrap();
+    format!("user_id={}, friend={}", user_id, friend)
+}
+
+// curl http://localhost:8088/extractor/query?username=xiaoming
+pub async fn extractor_query(info: web::Query<QueryInfo>) -> String {
+    format!("{:?}", info)
+}
+
+// 字段只通过 Debug 输出
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct JsonInfo {
+    username: String,
+}
+
+// curl -i -H 'Content-Type: application/json' -d '{"username": "xiaoming"}' -X POST http://localhost:8088/extractor/json 
+// curl -i -H 'Content-Type: application/json' -d '{"username": 1}' -X POST http://localhost:8088/extractor/json 
+pub async fn extractor_json(info: web::Json<JsonInfo>) -> String {
+    format!("{:?}", info)
+}
+
+// 字段只通过 Debug 输出
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct FormData {
+    username: String,
+}
+
+/// 使用serde提取表单数据
+/// 仅当内容类型为*x-www-form-urlencoded*时，才会调用此处理程序
+/// 并且请求的内容可以反序列化为FormData结构 
+// curl -i -H 'Content-Type: application/x-www-form-urlencoded' -d 'username=xiaoming' -X POST http://localhost:8088/extractor/form 
+pub async fn extractor_form(form: web::Form<FormData>) -> String {
+    format!("{:?}", form)
+}
@@ -0,0 +1,206 @@
+//! /healthz、/readyz、/status：供负载均衡和编排系统探测
+
+use actix::System;
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metrics;
+use crate::shutdown::Shutdown;
+use crate::{migration, PoolConnection, ResponseWrapper, Settings};
+
+const CHECK_OK: &str = "ok";
+
+/// 服务启动时间
+#[derive(Debug, Clone, Copy)]
+pub struct StartTime {
+    pub at: DateTime<Utc>,
+    instant: Instant,
+}
+
+impl StartTime {
+    pub fn now() -> Self {
+        StartTime { at: Utc::now(), instant: Instant::now() }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.instant.elapsed()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Liveness {
+    pub status: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    /// 检查项及结果，通过时为 `ok`
+    pub checks: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PoolStatus {
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub max_size: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ActorSystemStatus {
+    pub id: usize,
+    /// 系统 Arbiter 是否能在超时时间内执行任务
+    pub arbiter: String,
+    pub arbiter_latency_ms: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Status {
+    pub name: String,
+    /// 与 X-Version 响应头相同
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub shutting_down: bool,
+    pub pool: PoolStatus,
+    pub websocket_connections: i64,
+    pub actor_system: ActorSystemStatus,
+}
+
+// 进程存活即返回 200，不检查依赖
+// curl -i http://localhost:8088/healthz
+pub async fn healthz() -> ResponseWrapper<Liveness> {
+    ResponseWrapper::ok(Liveness { status: CHECK_OK })
+}
+
+// 可以接收流量时返回 200，否则返回 503，退出过程中始终返回 503
+// curl -i http://localhost:8088/readyz
+pub async fn readyz(
+    settings: web::Data<Settings>,
+    pool: web::Data<PoolConnection>,
+    shutdown: web::Data<Shutdown>,
+) -> ResponseWrapper<Readiness> {
+    let mut checks = BTreeMap::new();
+    if shutdown.is_shutting_down() {
+        // 退出时不再占用连接池
+        checks.insert("shutdown", "shutting down".to_string());
+    } else {
+        checks.insert("shutdown", CHECK_OK.to_string());
+        let timeout = Duration::from_secs(settings.server.readiness_timeout);
+        match check_database(pool.get_ref().clone(), timeout).await {
+            Ok(0) => {
+                checks.insert("database", CHECK_OK.to_string());
+                checks.insert("migrations", CHECK_OK.to_string());
+            }
+            Ok(pending) => {
+                checks.insert("database", CHECK_OK.to_string());
+                checks.insert("migrations", format!("{} pending", pending));
+            }
+            Err(e) => {
+                log::warn!("readiness check failed: {}", e);
+                checks.insert("database", e);
+            }
+        }
+    }
+
+    let ready = checks.len() == 3 && checks.values().all(|v| v == CHECK_OK);
+    let readiness = Readiness { ready, checks };
+    if ready {
+        ResponseWrapper::ok(readiness)
+    } else {
+        let mut resp = ResponseWrapper::err(50300, "not ready");
+        resp.data = Some(readiness);
+        resp
+    }
+}
+
+// 获取连接并执行 SELECT 1，返回未执行的迁移数
+async fn check_database(pool: PoolConnection, timeout: Duration) -> Result<usize, String> {
+    let check = web::block(move || {
+        let conn = pool.get_timeout(timeout).map_err(|e| e.to_string())?;
+        diesel::sql_query("SELECT 1").execute(&conn).map_err(|e| e.to_string())?;
+        migration::pending(&conn).map(|m| m.len()).map_err(|e| e.to_string())
+    });
+    match actix_rt::time::timeout(timeout, check).await {
+        Ok(Ok(pending)) => Ok(pending),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("timed out after {:?}", timeout)),
+    }
+}
+
+// curl -i http://localhost:8088/status
+pub async fn status(
+    settings: web::Data<Settings>,
+    pool: web::Data<PoolConnection>,
+    shutdown: web::Data<Shutdown>,
+    metrics: web::Data<Metrics>,
+    started: web::Data<StartTime>,
+) -> ResponseWrapper<Status> {
+    let state = pool.state();
+    let timeout = Duration::from_secs(settings.server.readiness_timeout);
+    ResponseWrapper::ok(Status {
+        name: settings.app.name.clone(),
+        version: settings.app.version.clone(),
+        started_at: started.at,
+        uptime_secs: started.uptime().as_secs(),
+        shutting_down: shutdown.is_shutting_down(),
+        pool: PoolStatus {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            max_size: pool.max_size(),
+        },
+        websocket_connections: metrics.ws_connections(),
+        actor_system: actor_system_status(timeout).await,
+    })
+}
+
+// 在系统 Arbiter 上执行一个空任务，检查 actor 系统是否能及时响应
+async fn actor_system_status(timeout: Duration) -> ActorSystemStatus {
+    let system = System::current();
+    let start = Instant::now();
+    let (arbiter, latency) = match actix_rt::time::timeout(timeout, system.arbiter().exec(|| ())).await {
+        Ok(Ok(())) => (CHECK_OK.to_string(), Some(start.elapsed().as_secs_f64() * 1000.0)),
+        Ok(Err(_)) => ("stopped".to_string(), None),
+        Err(_) => (format!("no response in {:?}", timeout), None),
+    };
+    ActorSystemStatus { id: system.id(), arbiter, arbiter_latency_ms: latency }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::app::testing::test_deps;
+    use crate::configure_app;
+    use actix_web::{http, test, App};
+
+    #[actix_rt::test]
+    async fn test_health() {
+        let deps = test_deps();
+        let mut app = test::init_service(App::new().configure(|cfg| configure_app(cfg, &deps))).await;
+
+        let req = test::TestRequest::get().uri("/healthz").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/readyz").to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["ready"], true, "{}", resp);
+
+        let req = test::TestRequest::get().uri("/status").to_request();
+        let resp: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(resp["data"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(resp["data"]["pool"]["max_size"], 1);
+        assert_eq!(resp["data"]["actor_system"]["arbiter"], "ok");
+
+        deps.shutdown.begin();
+        let req = test::TestRequest::get().uri("/readyz").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["code"], 50300);
+        assert_eq!(body["data"]["checks"]["shutdown"], "shutting down");
+    }
+}
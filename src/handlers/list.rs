@@ -0,0 +1,219 @@
+//! 列表接口通用的查询参数提取器
+
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use serde::Deserialize;
+
+use crate::{AppError, PageInfo};
+
+// 列表接口通用的分页、过滤、排序参数
+// 游标分页（按 id 的 keyset）：?limit=10&cursor=20
+// 偏移分页：?limit=10&offset=20
+// 过滤：?published=true&user_id=1&name_contains=ming
+// 排序：?sort=created_at:desc
+// 返回总数：?total=true
+#[derive(Deserialize, Debug)]
+pub struct RawListQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<i64>,
+    pub offset: Option<i64>,
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub total: bool,
+    pub published: Option<bool>,
+    pub user_id: Option<i64>,
+    pub name_contains: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    pub published: Option<bool>,
+    pub user_id: Option<i64>,
+    pub name_contains: Option<String>,
+}
+
+/// 校验后的列表参数，作为提取器使用
+#[derive(Debug, Clone)]
+pub struct ListQuery {
+    pub limit: i64,
+    pub cursor: Option<i64>,
+    pub offset: Option<i64>,
+    pub sort_field: String,
+    pub sort_order: SortOrder,
+    pub with_total: bool,
+    pub filter: ListFilter,
+}
+
+/// `name_contains` 等包含匹配的 `LIKE` 模式，转义 `%`、`_` 和 `\`，需要配合 `.escape('\\')` 使用
+pub fn like_contains(s: &str) -> String {
+    let mut pattern = String::with_capacity(s.len() + 2);
+    pattern.push('%');
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+pub const LIST_DEFAULT_LIMIT: i64 = 20;
+pub const LIST_MAX_LIMIT: i64 = 100;
+
+impl ListQuery {
+    pub fn from_raw(raw: RawListQuery) -> Result<Self, AppError> {
+        let limit = raw.limit.unwrap_or(LIST_DEFAULT_LIMIT);
+        if !(1..=LIST_MAX_LIMIT).contains(&limit) {
+            return Err(AppError::BadRequest(format!("limit must be between 1 and {}", LIST_MAX_LIMIT)));
+        }
+        if raw.cursor.is_some() && raw.offset.is_some() {
+            return Err(AppError::BadRequest("cursor and offset can not be used together".to_string()));
+        }
+        if matches!(raw.offset, Some(o) if o < 0) {
+            return Err(AppError::BadRequest("offset must not be negative".to_string()));
+        }
+
+        let sort = raw.sort.unwrap_or_else(|| "id".to_string());
+        let mut parts = sort.splitn(2, ':');
+        let sort_field = parts.next().unwrap_or_default().to_string();
+        let sort_order = match parts.next() {
+            None | Some("asc") => SortOrder::Asc,
+            Some("desc") => SortOrder::Desc,
+            Some(o) => return Err(AppError::BadRequest(format!("unknown sort order: {}", o))),
+        };
+        // keyset 分页只基于 id，按其他字段排序时游标没有意义
+        if raw.cursor.is_some() && sort_field != "id" {
+            return Err(AppError::BadRequest("cursor can only be used when sorting by id".to_string()));
+        }
+
+        Ok(ListQuery {
+            limit,
+            cursor: raw.cursor,
+            offset: raw.offset,
+            sort_field,
+            sort_order,
+            with_total: raw.total,
+            filter: ListFilter {
+                published: raw.published,
+                user_id: raw.user_id,
+                name_contains: raw.name_contains,
+            },
+        })
+    }
+
+    // 每个列表接口支持的排序字段不同
+    pub fn check_sort(&self, fields: &[&str]) -> Result<(), AppError> {
+        if fields.contains(&self.sort_field.as_str()) {
+            Ok(())
+        } else {
+            Err(AppError::BadRequest(format!("unknown sort field: {}", self.sort_field)))
+        }
+    }
+
+    // 多查一条用于判断是否还有下一页
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    // 截断多查的一条，并生成分页信息
+    pub fn paginate<T>(&self, mut rows: Vec<T>, total: Option<i64>, id_of: impl Fn(&T) -> i64) -> (Vec<T>, PageInfo) {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+        let mut page = PageInfo {
+            limit: self.limit,
+            total,
+            ..PageInfo::default()
+        };
+        if has_more {
+            // 游标只在按 id 排序时有效，其他排序方式继续使用偏移分页
+            if self.offset.is_some() || self.sort_field != "id" {
+                page.next_offset = Some(self.offset.unwrap_or(0) + self.limit);
+            } else {
+                page.next_cursor = rows.last().map(id_of);
+            }
+        }
+        (rows, page)
+    }
+}
+
+impl FromRequest for ListQuery {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            web::Query::<RawListQuery>::from_query(req.query_string())
+                .map_err(AppError::from)
+                .and_then(|raw| ListQuery::from_raw(raw.into_inner()))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::users::USER_SORT_FIELDS;
+
+    fn list_query(query: &str) -> Result<ListQuery, AppError> {
+        let raw = web::Query::<RawListQuery>::from_query(query).unwrap().into_inner();
+        ListQuery::from_raw(raw)
+    }
+
+    #[test]
+    fn test_list_query_parse() {
+        let q = list_query("").unwrap();
+        assert_eq!(q.limit, LIST_DEFAULT_LIMIT);
+        assert_eq!(q.sort_field, "id");
+        assert_eq!(q.sort_order, SortOrder::Asc);
+
+        let q = list_query("limit=5&sort=created_at:desc&total=true&name_contains=ming").unwrap();
+        assert_eq!(q.limit, 5);
+        assert_eq!(q.sort_field, "created_at");
+        assert_eq!(q.sort_order, SortOrder::Desc);
+        assert!(q.with_total);
+        assert_eq!(q.filter.name_contains.as_deref(), Some("ming"));
+
+        assert!(list_query("limit=0").is_err());
+        assert!(list_query("cursor=1&offset=1").is_err());
+        assert!(list_query("cursor=1&sort=name").is_err());
+        assert!(list_query("sort=id:up").is_err());
+        assert!(list_query("sort=title").unwrap().check_sort(USER_SORT_FIELDS).is_err());
+    }
+
+    #[test]
+    fn test_like_contains() {
+        assert_eq!(like_contains("ming"), "%ming%");
+        assert_eq!(like_contains(r"50%_\"), r"%50\%\_\\%");
+    }
+
+    #[test]
+    fn test_list_query_paginate() {
+        let q = list_query("limit=2").unwrap();
+        let (rows, page) = q.paginate(vec![1, 2, 3], None, |id| *id);
+        assert_eq!(rows, vec![1, 2]);
+        assert_eq!(page.next_cursor, Some(2));
+
+        let (rows, page) = q.paginate(vec![1, 2], Some(2), |id| *id);
+        assert_eq!(rows, vec![1, 2]);
+        assert_eq!(page, PageInfo { limit: 2, total: Some(2), ..PageInfo::default() });
+
+        let q = list_query("limit=2&offset=4").unwrap();
+        let (_, page) = q.paginate(vec![5, 6, 7], None, |id| *id);
+        assert_eq!(page.next_offset, Some(6));
+        assert_eq!(page.next_cursor, None);
+
+        let q = list_query("limit=2&sort=name").unwrap();
+        let (_, page) = q.paginate(vec![1, 2, 3], None, |id| *id);
+        assert_eq!(page.next_offset, Some(2));
+        assert_eq!(page.next_cursor, None);
+    }
+}
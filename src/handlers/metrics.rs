@@ -0,0 +1,22 @@
+//! /metrics：Prometheus 抓取接口
+
+use actix_web::{web, HttpResponse};
+
+use crate::counter::Counters;
+use crate::metrics::{self, Metrics};
+use crate::PoolConnection;
+
+// curl http://localhost:8088/metrics
+pub async fn metrics(
+    metrics: web::Data<Metrics>,
+    counters: web::Data<Counters>,
+    pool: web::Data<PoolConnection>,
+) -> HttpResponse {
+    let mut body = String::new();
+    metrics.render_prometheus(&mut body);
+    metrics::render_pool(&pool, &mut body);
+    counters.render_prometheus(&mut body);
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
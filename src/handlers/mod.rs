@@ -0,0 +1,29 @@
+//! 路由处理函数，按 scope 划分模块，路由注册见 `crate::app`
+
+use actix_web::web;
+
+use crate::{AppError, PoolConnection, PooledConnection};
+
+pub mod basic;
+pub mod responder;
+pub mod extractor;
+pub mod list;
+pub mod errors;
+pub mod ws;
+pub mod block;
+pub mod viewer;
+pub mod auth;
+pub mod admin;
+pub mod users;
+pub mod posts;
+pub mod counters;
+pub mod metrics;
+pub mod debug;
+pub mod health;
+
+// 在线程池中从连接池获取连接（r2d2 的 get 会阻塞直到超时）
+// 连接池耗尽时返回 503 和 Retry-After，而不是让 worker panic
+pub(crate) async fn db_conn(pool: &PoolConnection) -> Result<PooledConnection, AppError> {
+    let pool = pool.clone();
+    Ok(web::block(move || pool.get()).await?)
+}
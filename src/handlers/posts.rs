@@ -0,0 +1,291 @@
+//! /posts 以及 /users/{id}/posts
+
+use actix::Addr;
+use actix_web::{web, HttpRequest};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+
+use super::list::{ListFilter, ListQuery, SortOrder};
+use super::viewer::{CurrentUser, Viewer};
+use crate::authz::{self, Principal};
+use crate::events::{EventsQuery, PostEventKind, PostEvents, Publish, Subscribe};
+use crate::settings::EventsSettings;
+use crate::sse::{Event, Sse};
+use super::db_conn;
+use crate::{model, schema, AppError, DbBackend, DbConnection, PoolConnection, ResponseWrapper};
+
+pub const POST_SORT_FIELDS: &[&str] = &["id", "title"];
+
+pub type PostsQuery = schema::posts::BoxedQuery<'static, DbBackend>;
+
+// 文章列表的过滤条件，viewer 之外的用户只能看到已发布的文章
+pub fn filter_posts(filter: &ListFilter, viewer: Option<i64>) -> PostsQuery {
+    use schema::posts;
+    let mut query = posts::table.into_boxed();
+    if let Some(user_id) = filter.user_id {
+        query = query.filter(posts::user_id.eq(user_id));
+    }
+    if let Some(published) = filter.published {
+        query = query.filter(posts::published.eq(published));
+    }
+    match viewer {
+        Some(viewer_id) => query.filter(posts::published.eq(true).or(posts::user_id.eq(viewer_id))),
+        None => query.filter(posts::published.eq(true)),
+    }
+}
+
+pub fn page_posts(query: PostsQuery, q: &ListQuery) -> PostsQuery {
+    use schema::posts;
+    let mut query = match (q.sort_field.as_str(), q.sort_order) {
+        ("title", SortOrder::Asc) => query.order(posts::title.asc()),
+        ("title", SortOrder::Desc) => query.order(posts::title.desc()),
+        (_, SortOrder::Desc) => query.order(posts::id.desc()),
+        (_, SortOrder::Asc) => query.order(posts::id.asc()),
+    };
+    query = match q.sort_order {
+        SortOrder::Asc => query.then_order_by(posts::id.asc()),
+        SortOrder::Desc => query.then_order_by(posts::id.desc()),
+    };
+    query = match (q.cursor, q.sort_order) {
+        (Some(cursor), SortOrder::Asc) => query.filter(posts::id.gt(cursor)),
+        (Some(cursor), SortOrder::Desc) => query.filter(posts::id.lt(cursor)),
+        (None, _) => query,
+    };
+    if let Some(offset) = q.offset {
+        query = query.offset(offset);
+    }
+    query.limit(q.fetch_limit())
+}
+
+pub async fn load_visible_post(pool: &web::Data<PoolConnection>, viewer: &Viewer, id: i64) -> Result<model::Post, AppError> {
+    let conn = db_conn(pool).await?;
+
+    let post = web::block(move || {
+        use schema::posts;
+        posts::table
+            .find(id)
+            .first::<model::Post>(&conn)
+    }).await?;
+
+    if !post.published && !viewer.is(post.user_id) {
+        return Err(AppError::not_found());
+    }
+    Ok(post)
+}
+
+// 需要先登录，见 handlers::auth
+// curl -i -b cookies.txt -H 'Content-Type: application/json' -d '{"title": "hello", "body": "world"}' -X POST http://localhost:8088/users/1/posts
+pub async fn user_posts_create(
+    pool: web::Data<PoolConnection>,
+    events: web::Data<Addr<PostEvents>>,
+    current: CurrentUser,
+    user_id: web::Path<i64>,
+    post: web::Json<model::PostForInsert>,
+) -> Result<ResponseWrapper<model::Post>, AppError> {
+    let user_id = user_id.into_inner();
+    current.ensure_owner(user_id)?;
+    let conn = db_conn(&pool).await?;
+    let mut post = post.into_inner();
+    post.user_id = user_id;
+
+    let post = web::block(move || {
+        use schema::users;
+        conn.transaction(|| {
+            users::table
+                .find(user_id)
+                .first::<model::User>(&conn)?;
+            insert_post(&conn, &post)
+        })
+    }).await?;
+
+    events.do_send(Publish { kind: PostEventKind::Created, post: post.clone() });
+    Ok(ResponseWrapper::created(post))
+}
+
+// 插入文章并读回
+// PostgreSQL 使用 RETURNING，MySQL/SQLite 见 `db::last_insert_id`
+#[cfg(feature = "postgres")]
+fn insert_post(conn: &DbConnection, post: &model::PostForInsert) -> QueryResult<model::Post> {
+    use schema::posts;
+    diesel::insert_into(posts::table)
+        .values(post)
+        .get_result(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn insert_post(conn: &DbConnection, post: &model::PostForInsert) -> QueryResult<model::Post> {
+    use schema::posts;
+    diesel::insert_into(posts::table)
+        .values(post)
+        .execute(conn)?;
+    posts::table
+        .find(crate::db::last_insert_id(conn)?)
+        .first(conn)
+}
+
+// curl -i http://localhost:8088/users/1/posts
+// curl -i -b cookies.txt 'http://localhost:8088/users/1/posts?published=false'
+pub async fn user_posts_list(
+    pool: web::Data<PoolConnection>,
+    viewer: Viewer,
+    user_id: web::Path<i64>,
+    q: ListQuery,
+) -> Result<ResponseWrapper<Vec<model::Post>>, AppError> {
+    q.check_sort(POST_SORT_FIELDS)?;
+    let user_id = user_id.into_inner();
+    let conn = db_conn(&pool).await?;
+
+    let (posts, page) = web::block(move || {
+        use schema::users;
+        let user = users::table
+            .find(user_id)
+            .first::<model::User>(&conn)?;
+        let filter = ListFilter { user_id: Some(user.id), ..q.filter.clone() };
+        let total = if q.with_total {
+            Some(filter_posts(&filter, viewer.0).count().get_result::<i64>(&conn)?)
+        } else {
+            None
+        };
+        let posts = page_posts(filter_posts(&filter, viewer.0), &q)
+            .load::<model::Post>(&conn)?;
+        Ok::<_, DieselError>(q.paginate(posts, total, |p| p.id))
+    }).await?;
+
+    Ok(ResponseWrapper::paged(posts, page))
+}
+
+// curl -i 'http://localhost:8088/posts?published=true&user_id=1&sort=id:desc&limit=10'
+pub async fn posts_list(pool: web::Data<PoolConnection>, viewer: Viewer, q: ListQuery) -> Result<ResponseWrapper<Vec<model::Post>>, AppError> {
+    q.check_sort(POST_SORT_FIELDS)?;
+    let conn = db_conn(&pool).await?;
+
+    let (posts, page) = web::block(move || {
+        let total = if q.with_total {
+            Some(filter_posts(&q.filter, viewer.0).count().get_result::<i64>(&conn)?)
+        } else {
+            None
+        };
+        let posts = page_posts(filter_posts(&q.filter, viewer.0), &q)
+            .load::<model::Post>(&conn)?;
+        Ok::<_, DieselError>(q.paginate(posts, total, |p| p.id))
+    }).await?;
+
+    Ok(ResponseWrapper::paged(posts, page))
+}
+
+// curl -i http://localhost:8088/posts/1
+pub async fn posts_get(pool: web::Data<PoolConnection>, viewer: Viewer, id: web::Path<i64>) -> Result<ResponseWrapper<model::Post>, AppError> {
+    let post = load_visible_post(&pool, &viewer, id.into_inner()).await?;
+    Ok(ResponseWrapper::ok(post))
+}
+
+// curl -i -b cookies.txt -H 'Content-Type: application/json' -d '{"title": "hi"}' -X PATCH http://localhost:8088/posts/1
+pub async fn posts_update(
+    pool: web::Data<PoolConnection>,
+    events: web::Data<Addr<PostEvents>>,
+    current: CurrentUser,
+    id: web::Path<i64>,
+    changes: web::Json<model::PostForUpdate>,
+) -> Result<ResponseWrapper<model::Post>, AppError> {
+    let post = load_visible_post(&pool, &current.viewer(), id.into_inner()).await?;
+    current.ensure_owner(post.user_id)?;
+    let mut changes = changes.into_inner();
+    if changes.is_empty() {
+        return Ok(ResponseWrapper::ok(post));
+    }
+    changes.id = post.id;
+    let conn = db_conn(&pool).await?;
+
+    let post = web::block(move || {
+        use schema::posts;
+        diesel::update(&changes)
+            .set(&changes)
+            .execute(&conn)?;
+        posts::table
+            .find(changes.id)
+            .first::<model::Post>(&conn)
+    }).await?;
+
+    events.do_send(Publish { kind: PostEventKind::Updated, post: post.clone() });
+    Ok(ResponseWrapper::ok(post))
+}
+
+// curl -i -b cookies.txt -X POST http://localhost:8088/posts/1/publish
+pub async fn posts_publish(
+    pool: web::Data<PoolConnection>,
+    events: web::Data<Addr<PostEvents>>,
+    current: CurrentUser,
+    id: web::Path<i64>,
+) -> Result<ResponseWrapper<model::Post>, AppError> {
+    let post = load_visible_post(&pool, &current.viewer(), id.into_inner()).await?;
+    current.ensure_owner(post.user_id)?;
+    if post.published {
+        return Ok(ResponseWrapper::ok(post));
+    }
+    let conn = db_conn(&pool).await?;
+
+    let post = web::block(move || {
+        use schema::posts;
+        diesel::update(&post)
+            .set(posts::published.eq(true))
+            .execute(&conn)?;
+        posts::table
+            .find(post.id)
+            .first::<model::Post>(&conn)
+    }).await?;
+
+    events.do_send(Publish { kind: PostEventKind::Published, post: post.clone() });
+    Ok(ResponseWrapper::ok(post))
+}
+
+// 作者本人，或者有 posts:delete 权限的用户（如管理员）可以删除已发布的文章
+// curl -i -b cookies.txt -X DELETE http://localhost:8088/posts/1
+pub async fn posts_delete(
+    pool: web::Data<PoolConnection>,
+    events: web::Data<Addr<PostEvents>>,
+    principal: Principal,
+    id: web::Path<i64>,
+) -> Result<ResponseWrapper<()>, AppError> {
+    let post = load_visible_post(&pool, &Viewer(principal.user_id), id.into_inner()).await?;
+    principal.ensure_owner_or(post.user_id, authz::POSTS_DELETE)?;
+    let conn = db_conn(&pool).await?;
+
+    let deleted = post.clone();
+    web::block(move || {
+        diesel::delete(&post).execute(&conn)
+    }).await?;
+
+    events.do_send(Publish { kind: PostEventKind::Deleted, post: deleted });
+    Ok(ResponseWrapper::no_content())
+}
+
+// 文章变更通知（Server-Sent Events），过滤条件和断线重连见 crate::events
+// 空闲 events.keep_alive 秒后发送一行注释；订阅被断开（客户端处理不过来）时响应结束，客户端带上 Last-Event-ID 重连
+// curl -N -b cookies.txt 'http://localhost:8088/posts/events?user_id=1&kinds=created,published'
+// curl -N -H 'Last-Event-ID: 10' http://localhost:8088/posts/events
+pub async fn posts_events(
+    req: HttpRequest,
+    events: web::Data<Addr<PostEvents>>,
+    settings: web::Data<EventsSettings>,
+    viewer: Viewer,
+    q: web::Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Event>>, AppError> {
+    let filter = q.filter()?;
+    // 浏览器的 EventSource 重连时自动带上 Last-Event-ID，优先于查询参数
+    let last_event_id = match req.headers().get("Last-Event-ID") {
+        Some(value) => Some(
+            value.to_str().ok()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| AppError::BadRequest("invalid Last-Event-ID".to_string()))?,
+        ),
+        None => q.last_event_id,
+    };
+    let receiver = events
+        .send(Subscribe { viewer: viewer.0, filter, last_event_id })
+        .await
+        .map_err(AppError::internal)?;
+
+    Ok(Sse::new(receiver.map(|event| event.to_event())).keep_alive(Duration::from_secs(settings.keep_alive)))
+}
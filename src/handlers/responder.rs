@@ -0,0 +1,80 @@
+//! /responder：各种响应类型
+
+use actix_web::{web, Either, Error, HttpResponse, Responder};
+use futures::future::ok;
+use futures::stream::once;
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+
+use crate::sse::{Event, Sse};
+use crate::ResponseWrapper;
+
+// curl http://localhost:8088/responder/str
+pub async fn responder_str() -> &'static str {
+    "responder_str"
+}
+
+// curl http://localhost:8088/responder/string
+pub async fn responder_string() -> String {
+    "responder_string".to_owned()
+}
+
+// curl http://localhost:8088/responder/impl_responder
+pub async fn responder_impl_responder() -> impl Responder{
+    web::Bytes::from_static(b"responder_string")
+}
+
+// 自定义 Response 见 actix_learn::response::ResponseWrapper
+
+// curl http://localhost:8088/responder/custom_responder
+pub async fn responder_custom_responder() -> impl Responder {
+    ResponseWrapper::ok("custom_responder".to_string())
+}
+
+// curl http://localhost:8088/responder/stream
+pub async fn responder_stream_responder() -> HttpResponse {
+    let body = once(ok::<_, Error>(web::Bytes::from_static(b"test")));
+
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .streaming(body)
+}
+
+// Server-Sent Events，见 actix_learn::sse
+// 每秒发送一个事件，共 5 个；客户端断开后 interval 被 drop，不再产生事件
+// curl -N http://localhost:8088/responder/sse
+pub async fn responder_sse() -> Sse<impl Stream<Item = Event>> {
+    let ticks = actix_rt::time::interval(Duration::from_secs(1))
+        .enumerate()
+        .take(5)
+        .map(|(i, _)| Event::data(format!("tick {}", i)).id(i.to_string()).event("tick"));
+    Sse::new(ticks).retry(Duration::from_secs(3))
+}
+
+pub type RegisterResult = Either<HttpResponse, Result<&'static str, Error>>;
+
+// curl http://localhost:8088/responder/either
+pub async fn responder_either_responder() -> RegisterResult {
+    Either::A(HttpResponse::BadRequest().body("Bad data"))
+    // Either::B(Ok("Hello!"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use crate::Settings;
+
+    #[actix_rt::test]
+    async fn test_json_response() {
+        let mut app = test::init_service(
+            App::new()
+                .data(Settings::default())
+                .route("/responder/custom_responder", web::get().to(responder_custom_responder)),
+        ).await;
+        let req = test::TestRequest::get().uri("/responder/custom_responder").to_request();
+        let resp: ResponseWrapper<String> = test::read_response_json(&mut app, req).await;
+
+        assert_eq!(resp.code, 0);
+    }
+}
@@ -0,0 +1,219 @@
+//! /users
+
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use super::list::{like_contains, ListFilter, ListQuery, SortOrder};
+use super::viewer::Viewer;
+use super::db_conn;
+use crate::authz::{self, Principal};
+use crate::{model, schema, AppError, DbBackend, DbConnection, PoolConnection, ResponseWrapper};
+
+// 创建没有密码的用户，需要 users:manage 权限，普通用户通过 /auth/register 注册
+// curl -i -b cookies.txt -H 'Content-Type: application/json' -d '{"name": "xiaoming", "hair_color": "black"}' -X POST http://localhost:8088/users
+pub async fn users_create(
+    pool: web::Data<PoolConnection>,
+    principal: Principal,
+    user: web::Json<model::UserForInsert>,
+) -> Result<ResponseWrapper<model::User>, AppError> {
+    principal.ensure(&authz::permission(authz::USERS_MANAGE))?;
+    let conn = db_conn(&pool).await?;
+
+    let user = web::block(move || insert_user(&conn, &user.into_inner())).await?;
+
+    Ok(ResponseWrapper::created(user))
+}
+
+// 插入用户并读回
+// PostgreSQL 使用 RETURNING，MySQL/SQLite 见 `db::last_insert_id`
+#[cfg(feature = "postgres")]
+pub(crate) fn insert_user(conn: &DbConnection, user: &model::UserForInsert) -> QueryResult<model::User> {
+    use schema::users;
+    diesel::insert_into(users::table)
+        .values(user)
+        .get_result(conn)
+}
+
+#[cfg(not(feature = "postgres"))]
+pub(crate) fn insert_user(conn: &DbConnection, user: &model::UserForInsert) -> QueryResult<model::User> {
+    use schema::users;
+    diesel::insert_into(users::table)
+        .values(user)
+        .execute(conn)?;
+    users::table
+        .find(crate::db::last_insert_id(conn)?)
+        .first(conn)
+}
+
+pub const USER_SORT_FIELDS: &[&str] = &["id", "name", "created_at", "updated_at"];
+
+pub type UsersQuery = schema::users::BoxedQuery<'static, DbBackend>;
+
+// 用户列表的过滤条件，查询数据和统计总数共用
+pub fn filter_users(filter: &ListFilter) -> UsersQuery {
+    use schema::users;
+    let mut query = users::table.into_boxed();
+    if let Some(name) = &filter.name_contains {
+        query = query.filter(users::name.like(like_contains(name)).escape('\\'));
+    }
+    query
+}
+
+// 排序、游标与 limit/offset，排序字段已通过 check_sort 校验
+pub fn page_users(query: UsersQuery, q: &ListQuery) -> UsersQuery {
+    use schema::users;
+    let mut query = match (q.sort_field.as_str(), q.sort_order) {
+        ("name", SortOrder::Asc) => query.order(users::name.asc()),
+        ("name", SortOrder::Desc) => query.order(users::name.desc()),
+        ("created_at", SortOrder::Asc) => query.order(users::created_at.asc()),
+        ("created_at", SortOrder::Desc) => query.order(users::created_at.desc()),
+        ("updated_at", SortOrder::Asc) => query.order(users::updated_at.asc()),
+        ("updated_at", SortOrder::Desc) => query.order(users::updated_at.desc()),
+        (_, SortOrder::Desc) => query.order(users::id.desc()),
+        (_, SortOrder::Asc) => query.order(users::id.asc()),
+    };
+    // id 作为次要排序，保证排序稳定
+    query = match q.sort_order {
+        SortOrder::Asc => query.then_order_by(users::id.asc()),
+        SortOrder::Desc => query.then_order_by(users::id.desc()),
+    };
+    query = match (q.cursor, q.sort_order) {
+        (Some(cursor), SortOrder::Asc) => query.filter(users::id.gt(cursor)),
+        (Some(cursor), SortOrder::Desc) => query.filter(users::id.lt(cursor)),
+        (None, _) => query,
+    };
+    if let Some(offset) = q.offset {
+        query = query.offset(offset);
+    }
+    query.limit(q.fetch_limit())
+}
+
+// curl -i http://localhost:8088/users
+// curl -i 'http://localhost:8088/users?limit=10&cursor=20&name_contains=ming&total=true'
+// curl -i 'http://localhost:8088/users?limit=10&offset=20&sort=created_at:desc'
+pub async fn users_list(pool: web::Data<PoolConnection>, q: ListQuery) -> Result<ResponseWrapper<Vec<model::User>>, AppError> {
+    q.check_sort(USER_SORT_FIELDS)?;
+    let conn = db_conn(&pool).await?;
+
+    let (users, page) = web::block(move || {
+        let total = if q.with_total {
+            Some(filter_users(&q.filter).count().get_result::<i64>(&conn)?)
+        } else {
+            None
+        };
+        let users = page_users(filter_users(&q.filter), &q)
+            .load::<model::User>(&conn)?;
+        Ok::<_, DieselError>(q.paginate(users, total, |u| u.id))
+    }).await?;
+
+    Ok(ResponseWrapper::paged(users, page))
+}
+
+// curl -i http://localhost:8088/users/1
+pub async fn users_get(pool: web::Data<PoolConnection>, id: web::Path<i64>) -> Result<ResponseWrapper<model::User>, AppError> {
+    let conn = db_conn(&pool).await?;
+
+    let user = web::block(move || {
+        use schema::users;
+        users::table
+            .find(id.into_inner())
+            .first::<model::User>(&conn)
+    }).await?;
+
+    Ok(ResponseWrapper::ok(user))
+}
+
+// 只能修改自己，有 users:manage 权限时可以修改其他用户
+// 修改名字：curl -i -b cookies.txt -H 'Content-Type: application/json' -d '{"name": "xiaohong"}' -X PATCH http://localhost:8088/users/1
+// 清空发色：curl -i -b cookies.txt -H 'Content-Type: application/json' -d '{"hair_color": null}' -X PATCH http://localhost:8088/users/1
+pub async fn users_update(
+    pool: web::Data<PoolConnection>,
+    principal: Principal,
+    id: web::Path<i64>,
+    changes: web::Json<model::UserForUpdate>,
+) -> Result<ResponseWrapper<model::User>, AppError> {
+    let id = id.into_inner();
+    principal.ensure_owner_or(id, authz::USERS_MANAGE)?;
+    let conn = db_conn(&pool).await?;
+    let mut changes = changes.into_inner();
+    changes.id = id;
+
+    let user = web::block(move || {
+        use schema::users;
+        conn.transaction(|| {
+            // 先确认用户存在，不存在时返回 NotFound
+            let user = users::table
+                .find(changes.id)
+                .first::<model::User>(&conn)?;
+            if changes.is_empty() {
+                return Ok(user);
+            }
+            diesel::update(&changes)
+                .set(&changes)
+                .execute(&conn)?;
+            users::table
+                .find(changes.id)
+                .first::<model::User>(&conn)
+        })
+    }).await?;
+
+    Ok(ResponseWrapper::ok(user))
+}
+
+// 只能删除自己，有 users:manage 权限时可以删除其他用户
+// curl -i -b cookies.txt -X DELETE http://localhost:8088/users/1
+pub async fn users_delete(pool: web::Data<PoolConnection>, principal: Principal, id: web::Path<i64>) -> Result<ResponseWrapper<()>, AppError> {
+    let id = id.into_inner();
+    principal.ensure_owner_or(id, authz::USERS_MANAGE)?;
+    let conn = db_conn(&pool).await?;
+
+    web::block(move || {
+        use schema::{posts, user_roles, users};
+        // 同时删除该用户的文章
+        conn.transaction(|| {
+            diesel::delete(posts::table.filter(posts::user_id.eq(id))).execute(&conn)?;
+            diesel::delete(user_roles::table.filter(user_roles::user_id.eq(id))).execute(&conn)?;
+            match diesel::delete(users::table.find(id)).execute(&conn)? {
+                0 => Err(DieselError::NotFound),
+                _ => Ok(()),
+            }
+        })
+    }).await?;
+
+    Ok(ResponseWrapper::no_content())
+}
+
+// 一次查询一页用户，一次查询这些用户的文章，再用 grouped_by 在内存中分组，避免 N+1 查询
+// curl -i 'http://localhost:8088/users/with_posts?limit=10&cursor=20'
+pub async fn users_with_posts(pool: web::Data<PoolConnection>, viewer: Viewer, q: ListQuery) -> Result<ResponseWrapper<Vec<model::UserWithPosts>>, AppError> {
+    q.check_sort(USER_SORT_FIELDS)?;
+    let conn = db_conn(&pool).await?;
+
+    let (users, page) = web::block(move || {
+        use schema::posts;
+        let total = if q.with_total {
+            Some(filter_users(&q.filter).count().get_result::<i64>(&conn)?)
+        } else {
+            None
+        };
+        let users = page_users(filter_users(&q.filter), &q)
+            .load::<model::User>(&conn)?;
+        let (users, page) = q.paginate(users, total, |u| u.id);
+        let mut query = model::Post::belonging_to(&users)
+            .order(posts::id.asc())
+            .into_boxed();
+        query = match viewer.0 {
+            Some(viewer_id) => query.filter(posts::published.eq(true).or(posts::user_id.eq(viewer_id))),
+            None => query.filter(posts::published.eq(true)),
+        };
+        let posts = query.load::<model::Post>(&conn)?.grouped_by(&users);
+        let users = users.into_iter()
+            .zip(posts)
+            .map(|(user, posts)| model::UserWithPosts { user, posts })
+            .collect::<Vec<_>>();
+        Ok::<_, DieselError>((users, page))
+    }).await?;
+
+    Ok(ResponseWrapper::paged(users, page))
+}
@@ -0,0 +1,92 @@
+//! 当前访问者
+//!
+//! 用户 id 来自 `middleware::BearerAuth` 验证过的 Bearer token，没有 token 时读取签名的 cookie session
+//! （见 `handlers::auth`），handler 不需要关心使用了哪种认证方式。
+//! `Viewer` 不查询数据库，未登录也可以使用；`CurrentUser` 会从数据库加载用户，未登录时返回 401。
+
+use actix_session::UserSession;
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use diesel::prelude::*;
+use futures::future::{ready, LocalBoxFuture, Ready};
+
+use super::db_conn;
+use crate::token::BearerIdentity;
+use crate::{model, schema, AppError, PoolConnection};
+
+/// session 中保存用户 id 的 key
+pub const USER_ID_KEY: &str = "user_id";
+
+// session 无法解析时当作未登录，中间件中也会使用（ServiceRequest）
+pub(crate) fn current_user_id<R: HttpMessage + UserSession>(req: &R) -> Option<i64> {
+    if let Some(identity) = req.extensions().get::<BearerIdentity>() {
+        return Some(identity.user_id);
+    }
+    req.get_session().get::<i64>(USER_ID_KEY).unwrap_or(None)
+}
+
+/// 当前访问者，未登录时为 None
+pub struct Viewer(pub Option<i64>);
+
+impl FromRequest for Viewer {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Viewer(current_user_id(req))))
+    }
+}
+
+impl Viewer {
+    // 是否是 user_id 本人
+    pub fn is(&self, user_id: i64) -> bool {
+        self.0 == Some(user_id)
+    }
+}
+
+/// 已登录的用户，未登录或用户已被删除时返回 401
+pub struct CurrentUser(pub model::User);
+
+impl FromRequest for CurrentUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, AppError>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let user_id = current_user_id(req);
+        let pool = req.app_data::<web::Data<PoolConnection>>().cloned();
+        Box::pin(async move {
+            let user_id = user_id.ok_or_else(|| AppError::Unauthorized("login required".to_string()))?;
+            let pool = pool.ok_or_else(|| AppError::internal("PoolConnection is not registered"))?;
+            let conn = db_conn(&pool).await?;
+            let user = web::block(move || {
+                use schema::users;
+                users::table
+                    .find(user_id)
+                    .first::<model::User>(&conn)
+                    .optional()
+            }).await?;
+            user.map(CurrentUser)
+                .ok_or_else(|| AppError::Unauthorized("login required".to_string()))
+        })
+    }
+}
+
+impl CurrentUser {
+    pub fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    pub fn viewer(&self) -> Viewer {
+        Viewer(Some(self.0.id))
+    }
+
+    // 只有本人可以修改自己的资源
+    pub fn ensure_owner(&self, user_id: i64) -> Result<(), AppError> {
+        if self.0.id != user_id {
+            return Err(AppError::Forbidden("not the owner".to_string()));
+        }
+        Ok(())
+    }
+}
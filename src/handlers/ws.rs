@@ -0,0 +1,644 @@
+//! /ws：WebSocket
+
+use actix::{fut, Actor, ActorContext, ActorFuture, Addr, AsyncContext, ContextFutureSpawner, Handler, StreamHandler, WrapFuture};
+use actix_http::ws::{Codec, Item};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use std::time::{Duration, Instant};
+
+use super::viewer::current_user_id;
+use crate::chat::{ChatCommand, ChatEvent, ChatServer, Command, Connect, Disconnect};
+use crate::events::{EventsQuery, PostEvent, PostEvents, Subscribe};
+use crate::metrics::Metrics;
+use crate::rpc::{self, RpcContext, RpcEvent};
+use crate::settings::WsSettings;
+use crate::{AppError, PoolConnection};
+use crate::shutdown::{ServerShutdown, Shutdown};
+
+/// 重组后的完整消息
+#[derive(Debug, PartialEq)]
+pub enum WsMessage {
+    Text(String),
+    Binary(Bytes),
+}
+
+// 正在接收的分片消息
+struct Fragments {
+    text: bool,
+    data: BytesMut,
+}
+
+/// 连接数统计、优雅退出、心跳、空闲超时、Close 和分片重组，所有 WebSocket 会话共用
+pub struct WsConnection {
+    settings: WsSettings,
+    metrics: Metrics,
+    shutdown: Shutdown,
+    // 在 Shutdown 中注册的 ID
+    shutdown_id: Option<u64>,
+    /// 最后一次收到客户端的帧（包括 Pong）
+    last_seen: Instant,
+    fragments: Option<Fragments>,
+}
+
+/// 使用 `WsConnection` 的会话，`Handler<ServerShutdown>` 由 `impl_server_shutdown!` 实现
+pub trait WsSession: Actor<Context = ws::WebsocketContext<Self>> + Handler<ServerShutdown> {
+    fn connection(&mut self) -> &mut WsConnection;
+
+    /// 服务退出时在发送 Close 帧之前调用
+    fn shutting_down(&mut self, _ctx: &mut ws::WebsocketContext<Self>) {}
+}
+
+// 服务退出时发送 Close 帧并结束会话
+// Handler 是外部 trait，不能为所有 WsSession 统一实现
+macro_rules! impl_server_shutdown {
+    ($($session:ty),*) => {$(
+        impl Handler<ServerShutdown> for $session {
+            type Result = ();
+
+            fn handle(&mut self, _: ServerShutdown, ctx: &mut Self::Context) {
+                self.shutting_down(ctx);
+                close(ctx, ws::CloseCode::Away, "server shutting down");
+            }
+        }
+    )*};
+}
+
+impl WsConnection {
+    pub fn new(settings: WsSettings, metrics: Metrics, shutdown: Shutdown) -> Self {
+        WsConnection { settings, metrics, shutdown, shutdown_id: None, last_seen: Instant::now(), fragments: None }
+    }
+
+    /// 在 `started` 中调用：计入连接数，注册到 `Shutdown`，开始心跳
+    pub fn started<A: WsSession>(&mut self, ctx: &mut ws::WebsocketContext<A>) {
+        self.metrics.ws_connected();
+        self.shutdown_id = Some(self.shutdown.register(ctx.address().recipient()));
+        self.start_heartbeat(ctx);
+    }
+
+    /// 在 `stopped` 中调用
+    pub fn stopped(&mut self) {
+        self.metrics.ws_disconnected();
+        if let Some(id) = self.shutdown_id.take() {
+            self.shutdown.unregister(id);
+        }
+    }
+
+    // 定时发送 Ping，超过 `client_timeout` 没有收到任何帧时关闭连接
+    fn start_heartbeat<A: WsSession>(&self, ctx: &mut ws::WebsocketContext<A>) {
+        let timeout = Duration::from_secs(self.settings.client_timeout);
+        ctx.run_interval(Duration::from_secs(self.settings.heartbeat_interval), move |act, ctx| {
+            if act.connection().last_seen.elapsed() > timeout {
+                log::info!("websocket client timed out");
+                close(ctx, ws::CloseCode::Away, "client timeout");
+            } else {
+                ctx.ping(b"");
+            }
+        });
+    }
+
+    /// 处理控制帧和分片，返回完整的文本或二进制消息
+    ///
+    /// 收到 Close 时回复 Close 并停止会话；协议错误、消息过大或分片中的文本不是 UTF-8 时发送对应的 Close 帧并停止
+    pub fn handle<A>(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut ws::WebsocketContext<A>) -> Option<WsMessage>
+    where
+        A: Actor<Context = ws::WebsocketContext<A>>,
+    {
+        self.last_seen = Instant::now();
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::warn!("websocket protocol error: {}", e);
+                match e {
+                    ws::ProtocolError::Overflow => close(ctx, ws::CloseCode::Size, "message too large"),
+                    e => close(ctx, ws::CloseCode::Protocol, &e.to_string()),
+                }
+                return None;
+            }
+        };
+
+        match msg {
+            ws::Message::Ping(msg) => ctx.pong(&msg),
+            ws::Message::Pong(_) | ws::Message::Nop => {}
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            // 分片之间只能插入控制帧
+            ws::Message::Text(_) | ws::Message::Binary(_) if self.fragments.is_some() => {
+                close(ctx, ws::CloseCode::Protocol, "continuation not finished");
+            }
+            ws::Message::Text(text) => return Some(WsMessage::Text(text)),
+            ws::Message::Binary(bin) => return Some(WsMessage::Binary(bin)),
+            ws::Message::Continuation(item) => return self.continuation(item, ctx),
+        }
+        None
+    }
+
+    fn continuation<A>(&mut self, item: Item, ctx: &mut ws::WebsocketContext<A>) -> Option<WsMessage>
+    where
+        A: Actor<Context = ws::WebsocketContext<A>>,
+    {
+        let (first, data, last) = match item {
+            Item::FirstText(data) => (Some(true), data, false),
+            Item::FirstBinary(data) => (Some(false), data, false),
+            Item::Continue(data) => (None, data, false),
+            Item::Last(data) => (None, data, true),
+        };
+        match (first, &mut self.fragments) {
+            (Some(text), None) => self.fragments = Some(Fragments { text, data: BytesMut::new() }),
+            (None, Some(_)) => {}
+            // codec 已经检查过，这里不应该出现
+            _ => {
+                close(ctx, ws::CloseCode::Protocol, "unexpected continuation");
+                return None;
+            }
+        }
+
+        let fragments = self.fragments.as_mut().expect("fragments were just started");
+        if fragments.data.len() + data.len() > self.settings.max_message_size {
+            self.fragments = None;
+            close(ctx, ws::CloseCode::Size, "message too large");
+            return None;
+        }
+        fragments.data.extend_from_slice(&data);
+        if !last {
+            return None;
+        }
+
+        let fragments = self.fragments.take().expect("fragments were just started");
+        if !fragments.text {
+            return Some(WsMessage::Binary(fragments.data.freeze()));
+        }
+        match String::from_utf8(fragments.data.to_vec()) {
+            Ok(text) => Some(WsMessage::Text(text)),
+            Err(_) => {
+                close(ctx, ws::CloseCode::Invalid, "invalid utf-8");
+                None
+            }
+        }
+    }
+}
+
+fn close<A>(ctx: &mut ws::WebsocketContext<A>, code: ws::CloseCode, description: &str)
+where
+    A: Actor<Context = ws::WebsocketContext<A>>,
+{
+    ctx.close(Some(ws::CloseReason { code, description: Some(description.to_string()) }));
+    ctx.stop();
+}
+
+/// 握手并启动会话，单个帧的大小同样受 `max_message_size` 限制
+pub fn start<A>(actor: A, settings: &WsSettings, req: &HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error>
+where
+    A: Actor<Context = ws::WebsocketContext<A>> + StreamHandler<Result<ws::Message, ws::ProtocolError>>,
+{
+    let codec = Codec::new().max_size(settings.max_message_size);
+    Ok(ws::handshake(req)?.streaming(ws::WebsocketContext::with_codec(actor, stream, codec)))
+}
+
+impl_server_shutdown!(MyWs, ChatSession, RpcSession, PostsSession);
+
+/// 定义Http Actor
+pub struct MyWs {
+    conn: WsConnection,
+}
+
+impl MyWs {
+    pub fn new(metrics: Metrics, shutdown: Shutdown, settings: WsSettings) -> Self {
+        MyWs { conn: WsConnection::new(settings, metrics, shutdown) }
+    }
+}
+
+impl WsSession for MyWs {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.conn
+    }
+}
+
+impl Actor for MyWs {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.conn.started(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.conn.stopped();
+    }
+}
+
+/// 一个 ws::Message 消息的 处理器
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MyWs {
+    fn handle(
+        &mut self,
+        msg: Result<ws::Message, ws::ProtocolError>,
+        ctx: &mut Self::Context,
+    ) {
+        match self.conn.handle(msg, ctx) {
+            Some(WsMessage::Text(text)) => ctx.text(text),
+            Some(WsMessage::Binary(bin)) => ctx.binary(bin),
+            None => (),
+        }
+    }
+}
+
+// ws://localhost:8088/ws/echo
+/*
+curl --include \
+     --no-buffer \
+     --header "Connection: Upgrade" \
+     --header "Upgrade: websocket" \
+     --header "Host: echo.websocket.org" \
+     --header "Origin: https://echo.websocket.org" \
+     --header "Sec-WebSocket-Key: NVwjmQUcWCenfWu98asDmg==" \
+     --header "Sec-WebSocket-Version: 13" \
+     http://localhost:8088/ws/echo
+*/
+pub async fn ws_echo(
+    req: HttpRequest,
+    stream: web::Payload,
+    metrics: web::Data<Metrics>,
+    shutdown: web::Data<Shutdown>,
+    settings: web::Data<WsSettings>,
+) -> Result<HttpResponse, Error> {
+    let session = MyWs::new(metrics.get_ref().clone(), shutdown.get_ref().clone(), settings.get_ref().clone());
+    start(session, &settings, &req, stream)
+}
+
+/// 聊天会话，命令转发给 `ChatServer`，见 `crate::chat`
+pub struct ChatSession {
+    /// `ChatServer` 分配的 ID，连接成功前为 0
+    id: u64,
+    user_id: Option<i64>,
+    server: Addr<ChatServer>,
+    conn: WsConnection,
+}
+
+impl ChatSession {
+    pub fn new(server: Addr<ChatServer>, user_id: Option<i64>, metrics: Metrics, shutdown: Shutdown, settings: WsSettings) -> Self {
+        ChatSession { id: 0, user_id, server, conn: WsConnection::new(settings, metrics, shutdown) }
+    }
+
+    fn send_event(&self, event: &ChatEvent, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::to_string(event) {
+            Ok(text) => ctx.text(text),
+            Err(e) => log::error!("failed to serialize chat event: {}", e),
+        }
+    }
+}
+
+impl WsSession for ChatSession {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.conn
+    }
+}
+
+impl Actor for ChatSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.conn.started(ctx);
+        // 注册完成前不处理客户端的消息
+        self.server
+            .send(Connect { session: ctx.address().recipient(), user_id: self.user_id })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(id) => act.id = id,
+                    Err(_) => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.conn.stopped();
+        self.server.do_send(Disconnect { id: self.id });
+    }
+}
+
+impl Handler<ChatEvent> for ChatSession {
+    type Result = ();
+
+    fn handle(&mut self, event: ChatEvent, ctx: &mut Self::Context) {
+        self.send_event(&event, ctx);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSession {
+    fn handle(
+        &mut self,
+        msg: Result<ws::Message, ws::ProtocolError>,
+        ctx: &mut Self::Context,
+    ) {
+        match self.conn.handle(msg, ctx) {
+            Some(WsMessage::Text(text)) => match serde_json::from_str::<ChatCommand>(&text) {
+                Ok(command) => self.server.do_send(Command { id: self.id, command }),
+                Err(e) => self.send_event(&ChatEvent::error(format!("invalid command: {}", e)), ctx),
+            },
+            Some(WsMessage::Binary(_)) => self.send_event(&ChatEvent::error("binary frames are not supported"), ctx),
+            None => (),
+        }
+    }
+}
+
+// 登录后（cookie 或 Bearer token）发送的消息带有 user_id
+// websocat ws://localhost:8088/ws/chat
+// {"cmd": "join", "room": "posts/1"}
+pub async fn ws_chat(
+    req: HttpRequest,
+    stream: web::Payload,
+    server: web::Data<Addr<ChatServer>>,
+    metrics: web::Data<Metrics>,
+    shutdown: web::Data<Shutdown>,
+    settings: web::Data<WsSettings>,
+) -> Result<HttpResponse, Error> {
+    let session = ChatSession::new(
+        server.get_ref().clone(),
+        current_user_id(&req),
+        metrics.get_ref().clone(),
+        shutdown.get_ref().clone(),
+        settings.get_ref().clone(),
+    );
+    start(session, &settings, &req, stream)
+}
+
+/// JSON 请求/响应会话，见 `crate::rpc`
+pub struct RpcSession {
+    rpc: RpcContext,
+    conn: WsConnection,
+}
+
+impl RpcSession {
+    pub fn new(rpc: RpcContext, metrics: Metrics, shutdown: Shutdown, settings: WsSettings) -> Self {
+        RpcSession { rpc, conn: WsConnection::new(settings, metrics, shutdown) }
+    }
+
+    fn send_event(&self, event: &RpcEvent, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::to_string(event) {
+            Ok(text) => ctx.text(text),
+            Err(e) => log::error!("failed to serialize rpc event: {}", e),
+        }
+    }
+}
+
+impl WsSession for RpcSession {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.conn
+    }
+
+    fn shutting_down(&mut self, ctx: &mut Self::Context) {
+        self.send_event(&RpcEvent::new("shutdown", serde_json::Value::Null), ctx);
+    }
+}
+
+impl Actor for RpcSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.conn.started(ctx);
+        let ready = RpcEvent::new("ready", serde_json::json!({ "user_id": self.rpc.user_id }));
+        self.send_event(&ready, ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.conn.stopped();
+    }
+}
+
+impl Handler<RpcEvent> for RpcSession {
+    type Result = ();
+
+    fn handle(&mut self, event: RpcEvent, ctx: &mut Self::Context) {
+        self.send_event(&event, ctx);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RpcSession {
+    fn handle(
+        &mut self,
+        msg: Result<ws::Message, ws::ProtocolError>,
+        ctx: &mut Self::Context,
+    ) {
+        match self.conn.handle(msg, ctx) {
+            // 请求并发执行，会话停止时未完成的请求被丢弃
+            Some(WsMessage::Text(text)) => match rpc::parse(&text) {
+                Ok(req) => {
+                    ctx.spawn(rpc::dispatch(self.rpc.clone(), req).into_actor(self).map(|resp, _, ctx| ctx.text(resp)));
+                }
+                Err(resp) => ctx.text(resp),
+            },
+            Some(WsMessage::Binary(_)) => {
+                ctx.text(rpc::error(None, AppError::BadRequest("binary frames are not supported".to_string())))
+            }
+            None => (),
+        }
+    }
+}
+
+// 登录后（cookie 或 Bearer token）可以调用 me
+// websocat ws://localhost:8088/ws/rpc
+// {"id": 1, "method": "users.get", "params": {"id": 1}}
+pub async fn ws_rpc(
+    req: HttpRequest,
+    stream: web::Payload,
+    pool: web::Data<PoolConnection>,
+    metrics: web::Data<Metrics>,
+    shutdown: web::Data<Shutdown>,
+    settings: web::Data<WsSettings>,
+) -> Result<HttpResponse, Error> {
+    let rpc = RpcContext { pool: pool.clone(), user_id: current_user_id(&req) };
+    let session = RpcSession::new(rpc, metrics.get_ref().clone(), shutdown.get_ref().clone(), settings.get_ref().clone());
+    start(session, &settings, &req, stream)
+}
+
+/// 文章变更通知，每个事件是一个 JSON 文本帧，见 `crate::events`
+pub struct PostsSession {
+    events: Addr<PostEvents>,
+    /// 在 `started` 中发送
+    subscribe: Option<Subscribe>,
+    conn: WsConnection,
+}
+
+impl PostsSession {
+    pub fn new(events: Addr<PostEvents>, subscribe: Subscribe, metrics: Metrics, shutdown: Shutdown, settings: WsSettings) -> Self {
+        PostsSession { events, subscribe: Some(subscribe), conn: WsConnection::new(settings, metrics, shutdown) }
+    }
+}
+
+impl WsSession for PostsSession {
+    fn connection(&mut self) -> &mut WsConnection {
+        &mut self.conn
+    }
+}
+
+impl Actor for PostsSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.conn.started(ctx);
+        let subscribe = self.subscribe.take().expect("subscribe only once");
+        self.events
+            .send(subscribe)
+            .into_actor(self)
+            .then(|res, _, ctx| {
+                match res {
+                    Ok(receiver) => {
+                        ctx.add_stream(receiver);
+                    }
+                    Err(_) => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.conn.stopped();
+    }
+}
+
+impl StreamHandler<PostEvent> for PostsSession {
+    fn handle(&mut self, event: PostEvent, ctx: &mut Self::Context) {
+        match serde_json::to_string(&event) {
+            Ok(text) => ctx.text(text),
+            Err(e) => log::error!("failed to serialize post event: {}", e),
+        }
+    }
+
+    // 订阅被断开，客户端需要带上 last_event_id 重连
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        close(ctx, ws::CloseCode::Policy, "subscriber too slow");
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for PostsSession {
+    fn handle(
+        &mut self,
+        msg: Result<ws::Message, ws::ProtocolError>,
+        ctx: &mut Self::Context,
+    ) {
+        // 只推送，忽略客户端的消息
+        self.conn.handle(msg, ctx);
+    }
+}
+
+// 参数与 /posts/events 相同，WebSocket 没有 Last-Event-ID 请求头，使用 last_event_id 参数
+// websocat 'ws://localhost:8088/ws/posts?user_id=1&kinds=created,published&last_event_id=10'
+pub async fn ws_posts(
+    req: HttpRequest,
+    stream: web::Payload,
+    events: web::Data<Addr<PostEvents>>,
+    q: web::Query<EventsQuery>,
+    metrics: web::Data<Metrics>,
+    shutdown: web::Data<Shutdown>,
+    settings: web::Data<WsSettings>,
+) -> Result<HttpResponse, Error> {
+    let subscribe = Subscribe { viewer: current_user_id(&req), filter: q.filter()?, last_event_id: q.last_event_id };
+    let session = PostsSession::new(
+        events.get_ref().clone(),
+        subscribe,
+        metrics.get_ref().clone(),
+        shutdown.get_ref().clone(),
+        settings.get_ref().clone(),
+    );
+    start(session, &settings, &req, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::ws::{Frame, Message};
+    use actix_web::{test, App};
+    use futures::{SinkExt, StreamExt};
+
+    fn server(settings: WsSettings) -> test::TestServer {
+        test::start(move || {
+            App::new()
+                .data(Metrics::new())
+                .data(Shutdown::new())
+                .data(settings.clone())
+                .route("/ws", web::get().to(ws_echo))
+        })
+    }
+
+    fn close_code(frame: Option<Result<Frame, ws::ProtocolError>>) -> ws::CloseCode {
+        match frame {
+            Some(Ok(Frame::Close(Some(reason)))) => reason.code,
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_continuation_and_close() {
+        let mut srv = server(WsSettings { max_message_size: 16, ..WsSettings::default() });
+
+        let mut framed = srv.ws_at("/ws").await.unwrap();
+        framed.send(Message::Text("hi".to_string())).await.unwrap();
+        assert_eq!(framed.next().await.unwrap().unwrap(), Frame::Text(Bytes::from_static(b"hi")));
+
+        // actix-http 1.0.1 编码时把 FirstText 和 FirstBinary 的 opcode 写反了，客户端需要反过来发送
+        let first_text = |data| Message::Continuation(Item::FirstBinary(Bytes::from_static(data)));
+        let first_binary = |data| Message::Continuation(Item::FirstText(Bytes::from_static(data)));
+
+        // 分片之间可以插入 Ping
+        framed.send(first_text(b"hello ")).await.unwrap();
+        framed.send(Message::Ping(Bytes::from_static(b"p"))).await.unwrap();
+        assert_eq!(framed.next().await.unwrap().unwrap(), Frame::Pong(Bytes::from_static(b"p")));
+        framed.send(Message::Continuation(Item::Continue(Bytes::from_static(b"wor")))).await.unwrap();
+        framed.send(Message::Continuation(Item::Last(Bytes::from_static(b"ld")))).await.unwrap();
+        assert_eq!(framed.next().await.unwrap().unwrap(), Frame::Text(Bytes::from_static(b"hello world")));
+
+        framed.send(first_binary(&[1, 2])).await.unwrap();
+        framed.send(Message::Continuation(Item::Last(Bytes::from_static(&[3])))).await.unwrap();
+        assert_eq!(framed.next().await.unwrap().unwrap(), Frame::Binary(Bytes::from_static(&[1, 2, 3])));
+
+        // 回复 Close 后断开
+        framed.send(Message::Close(Some(ws::CloseCode::Normal.into()))).await.unwrap();
+        assert_eq!(close_code(framed.next().await), ws::CloseCode::Normal);
+        assert!(framed.next().await.is_none());
+
+        // 重组后超过 max_message_size
+        let mut framed = srv.ws_at("/ws").await.unwrap();
+        framed.send(first_text(b"0123456789")).await.unwrap();
+        framed.send(Message::Continuation(Item::Last(Bytes::from_static(b"0123456789")))).await.unwrap();
+        assert_eq!(close_code(framed.next().await), ws::CloseCode::Size);
+
+        // 单个帧超过 max_message_size
+        let mut framed = srv.ws_at("/ws").await.unwrap();
+        framed.send(Message::Text("01234567890123456789".to_string())).await.unwrap();
+        assert_eq!(close_code(framed.next().await), ws::CloseCode::Size);
+
+        // 分片没有结束时发送新消息
+        let mut framed = srv.ws_at("/ws").await.unwrap();
+        framed.send(first_text(b"a")).await.unwrap();
+        framed.send(Message::Text("b".to_string())).await.unwrap();
+        assert_eq!(close_code(framed.next().await), ws::CloseCode::Protocol);
+    }
+
+    #[actix_rt::test]
+    async fn test_heartbeat() {
+        let mut srv = server(WsSettings { heartbeat_interval: 1, client_timeout: 2, ..WsSettings::default() });
+        let mut framed = srv.ws_at("/ws").await.unwrap();
+
+        // 回复前两个 Ping，之后不再回复，连接因为超时被关闭
+        let mut pings = 0;
+        loop {
+            match framed.next().await {
+                Some(Ok(Frame::Ping(_))) => {
+                    pings += 1;
+                    if pings <= 2 {
+                        framed.send(Message::Pong(Bytes::new())).await.unwrap();
+                    }
+                }
+                frame => {
+                    assert_eq!(close_code(frame), ws::CloseCode::Away);
+                    break;
+                }
+            }
+        }
+        assert!(pings >= 3, "{}", pings);
+    }
+}
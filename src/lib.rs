@@ -3,24 +3,64 @@ extern crate diesel;
 extern crate dotenv;
 
 use diesel::prelude::*;
-use dotenv::dotenv;
 use diesel::r2d2;
-use std::env;
 
 pub mod schema;
 pub mod model;
+pub mod migration;
+pub mod db;
+pub mod error;
+pub mod response;
+pub mod sse;
+pub mod password;
+pub mod token;
+pub mod authz;
+pub mod session;
+pub mod settings;
+pub mod logging;
+pub mod counter;
+pub mod trace;
+pub mod shutdown;
+pub mod chat;
+pub mod rpc;
+pub mod events;
+pub mod metrics;
+pub mod middleware;
+pub mod handlers;
+pub mod app;
 
-pub type PoolConnection = r2d2::Pool<r2d2::ConnectionManager<MysqlConnection>>;
+pub use app::{configure_app, AppDeps};
+pub use db::{establish_connection, new_connection_pool, DbConfig, DbError};
+pub use error::AppError;
+pub use metrics::Metrics;
+pub use response::{PageInfo, ResponseWrapper};
+pub use settings::{Settings, SettingsArgs, SettingsError};
 
-pub fn new_connection_pool() ->  PoolConnection {
-    dotenv().ok();
+#[cfg(any(
+    all(feature = "mysql", feature = "sqlite"),
+    all(feature = "mysql", feature = "postgres"),
+    all(feature = "sqlite", feature = "postgres"),
+))]
+compile_error!("features `mysql`, `sqlite` and `postgres` are mutually exclusive");
 
-    let database_url = env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set");
-    let manager = r2d2::ConnectionManager::<MysqlConnection>::new(database_url);
-    let pool = r2d2::Pool::builder()
-        .max_size(15)
-        .build(manager)
-        .expect("Failed to create pool.");
-    pool
-}
+#[cfg(not(any(feature = "mysql", feature = "sqlite", feature = "postgres")))]
+compile_error!("one of the features `mysql`, `sqlite` or `postgres` must be enabled");
+
+// 编译期选择的数据库连接类型与后端
+#[cfg(feature = "mysql")]
+pub type DbConnection = MysqlConnection;
+#[cfg(feature = "mysql")]
+pub type DbBackend = diesel::mysql::Mysql;
+
+#[cfg(feature = "sqlite")]
+pub type DbConnection = SqliteConnection;
+#[cfg(feature = "sqlite")]
+pub type DbBackend = diesel::sqlite::Sqlite;
+
+#[cfg(feature = "postgres")]
+pub type DbConnection = PgConnection;
+#[cfg(feature = "postgres")]
+pub type DbBackend = diesel::pg::Pg;
+
+pub type PoolConnection = r2d2::Pool<r2d2::ConnectionManager<DbConnection>>;
+pub type PooledConnection = r2d2::PooledConnection<r2d2::ConnectionManager<DbConnection>>;
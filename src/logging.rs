@@ -0,0 +1,108 @@
+//! 日志初始化和访问日志
+//!
+//! `log.format = "json"` 时所有日志都输出为单行 JSON，便于日志系统采集；
+//! 访问日志由 `middleware::AccessLog` 记录，target 为 [`ACCESS_LOG_TARGET`]。
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::settings::{LogFormat, LogSettings};
+
+/// 访问日志的 target，可以在 `log.filter` 中单独控制，例如 `actix_learn::access=off`
+pub const ACCESS_LOG_TARGET: &str = "actix_learn::access";
+
+/// 初始化 env_logger，只能调用一次
+pub fn init(settings: &LogSettings) {
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&settings.filter);
+    if settings.format == LogFormat::Json {
+        builder.format(|buf, record| {
+            // 访问日志本身就是 JSON
+            if record.target() == ACCESS_LOG_TARGET {
+                return writeln!(buf, "{}", record.args());
+            }
+            let line = serde_json::json!({
+                "ts": now(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "msg": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// 一条访问日志
+#[derive(Debug, Serialize)]
+pub struct AccessEntry {
+    pub method: String,
+    pub path: String,
+    /// 匹配到的路由模板，见 `crate::metrics::route_pattern`
+    pub route: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// 响应体字节数，流式响应为 None
+    pub bytes: Option<u64>,
+    pub request_id: Option<String>,
+    pub peer: Option<String>,
+}
+
+impl AccessEntry {
+    pub fn render(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => format!(
+                "{} \"{} {}\" {} {} {:.3}ms request_id={}",
+                self.peer.as_deref().unwrap_or("-"),
+                self.method,
+                self.path,
+                self.status,
+                self.bytes.map(|b| b.to_string()).as_deref().unwrap_or("-"),
+                self.latency_ms,
+                self.request_id.as_deref().unwrap_or("-"),
+            ),
+            LogFormat::Json => {
+                #[derive(Serialize)]
+                struct Line<'a> {
+                    ts: String,
+                    #[serde(flatten)]
+                    entry: &'a AccessEntry,
+                }
+                serde_json::to_string(&Line { ts: now(), entry: self }).unwrap_or_default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let entry = AccessEntry {
+            method: "GET".to_string(),
+            path: "/users/1".to_string(),
+            route: "/users/{id}".to_string(),
+            status: 200,
+            latency_ms: 1.5,
+            bytes: Some(42),
+            request_id: Some("abc".to_string()),
+            peer: None,
+        };
+        assert_eq!(entry.render(LogFormat::Text), "- \"GET /users/1\" 200 42 1.500ms request_id=abc");
+
+        let line: serde_json::Value = serde_json::from_str(&entry.render(LogFormat::Json)).unwrap();
+        assert_eq!(line["route"], "/users/{id}");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["bytes"], 42);
+        assert_eq!(line["request_id"], "abc");
+        assert_eq!(line["peer"], serde_json::Value::Null);
+        assert!(line["ts"].is_string());
+    }
+}
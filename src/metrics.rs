@@ -0,0 +1,283 @@
+//! Prometheus 指标
+//!
+//! 请求指标由 `middleware::RequestMetrics` 记录，route 标签使用匹配到的路由模板
+//! （如 `/extractor/path/{user_id}/{friend}`），没有匹配到任何路由的请求统一记为 `unmatched`，
+//! 避免标签数量随请求路径无限增长。
+//!
+//! actix-web 2.0 只有在路由之后才知道路由模板，所以进行中的请求数只按 method 区分。
+
+use actix_web::HttpRequest;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::PoolConnection;
+
+/// 请求耗时直方图的桶（秒）
+pub const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// 没有匹配到路由时的 route 标签
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct RequestKey {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Default)]
+struct RequestStats {
+    count: AtomicU64,
+    // 每个桶单独计数，输出时再累加
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    sum_micros: AtomicU64,
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: RwLock<HashMap<RequestKey, Arc<RequestStats>>>,
+    in_flight: RwLock<HashMap<String, Arc<AtomicI64>>>,
+    ws_connections: AtomicI64,
+    ws_connections_total: AtomicU64,
+}
+
+/// 进程内共享的指标，clone 后共享同一份数据
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+/// 进行中的请求，drop 时计数减一（包括请求被取消的情况）
+pub struct InFlightGuard(Arc<AtomicI64>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn in_flight(&self, method: &str) -> InFlightGuard {
+        let gauge = self.inner.in_flight.read().unwrap().get(method).cloned();
+        let gauge = match gauge {
+            Some(gauge) => gauge,
+            None => self.inner.in_flight.write().unwrap()
+                .entry(method.to_string())
+                .or_default()
+                .clone(),
+        };
+        gauge.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(gauge)
+    }
+
+    /// 记录一个完成的请求
+    pub fn observe(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let key = RequestKey { method: method.to_string(), route: route.to_string(), status };
+        let stats = self.inner.requests.read().unwrap().get(&key).cloned();
+        let stats = match stats {
+            Some(stats) => stats,
+            None => self.inner.requests.write().unwrap().entry(key).or_default().clone(),
+        };
+
+        stats.count.fetch_add(1, Ordering::Relaxed);
+        stats.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            stats.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn ws_connected(&self) {
+        self.inner.ws_connections.fetch_add(1, Ordering::Relaxed);
+        self.inner.ws_connections_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn ws_disconnected(&self) {
+        self.inner.ws_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// 当前的 WebSocket 连接数
+    pub fn ws_connections(&self) -> i64 {
+        self.inner.ws_connections.load(Ordering::Relaxed)
+    }
+
+    /// Prometheus 文本格式
+    pub fn render_prometheus(&self, out: &mut String) {
+        let mut requests = self.inner.requests.read().unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        requests.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let _ = writeln!(out, "# HELP http_requests_total Total number of HTTP requests.");
+        let _ = writeln!(out, "# TYPE http_requests_total counter");
+        for (key, stats) in &requests {
+            let _ = writeln!(
+                out, "http_requests_total{{{}}} {}",
+                key.labels(), stats.count.load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(out, "# HELP http_request_duration_seconds HTTP request latency.");
+        let _ = writeln!(out, "# TYPE http_request_duration_seconds histogram");
+        for (key, stats) in &requests {
+            let labels = key.labels();
+            let mut cumulative = 0;
+            for (le, bucket) in LATENCY_BUCKETS.iter().zip(stats.buckets.iter()) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let _ = writeln!(
+                    out, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let count = stats.count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, count);
+            let _ = writeln!(
+                out, "http_request_duration_seconds_sum{{{}}} {}",
+                labels, stats.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+            );
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, count);
+        }
+
+        let mut in_flight = self.inner.in_flight.read().unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        in_flight.sort();
+        let _ = writeln!(out, "# HELP http_requests_in_flight Number of HTTP requests being served.");
+        let _ = writeln!(out, "# TYPE http_requests_in_flight gauge");
+        for (method, value) in in_flight {
+            let _ = writeln!(out, "http_requests_in_flight{{method=\"{}\"}} {}", escape(&method), value);
+        }
+
+        let _ = writeln!(out, "# HELP websocket_connections Number of open WebSocket connections.");
+        let _ = writeln!(out, "# TYPE websocket_connections gauge");
+        let _ = writeln!(out, "websocket_connections {}", self.ws_connections());
+        let _ = writeln!(out, "# HELP websocket_connections_total Total number of WebSocket connections.");
+        let _ = writeln!(out, "# TYPE websocket_connections_total counter");
+        let _ = writeln!(out, "websocket_connections_total {}", self.inner.ws_connections_total.load(Ordering::Relaxed));
+    }
+}
+
+impl RequestKey {
+    fn labels(&self) -> String {
+        format!(
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            escape(&self.method), escape(&self.route), self.status
+        )
+    }
+}
+
+/// 连接池状态
+pub fn render_pool(pool: &PoolConnection, out: &mut String) {
+    let state = pool.state();
+    let _ = writeln!(out, "# HELP db_pool_connections Number of connections in the pool.");
+    let _ = writeln!(out, "# TYPE db_pool_connections gauge");
+    let _ = writeln!(out, "db_pool_connections {}", state.connections);
+    let _ = writeln!(out, "# HELP db_pool_idle_connections Number of idle connections in the pool.");
+    let _ = writeln!(out, "# TYPE db_pool_idle_connections gauge");
+    let _ = writeln!(out, "db_pool_idle_connections {}", state.idle_connections);
+    let _ = writeln!(out, "# HELP db_pool_max_connections Maximum number of connections in the pool.");
+    let _ = writeln!(out, "# TYPE db_pool_max_connections gauge");
+    let _ = writeln!(out, "db_pool_max_connections {}", pool.max_size());
+}
+
+// label 值中的 \ " 和换行需要转义
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// 根据路由匹配结果还原路由模板，没有匹配到任何路由时返回 None
+///
+/// actix-web 2.0 不保存匹配到的模板，这里把路径中与参数值相同的段替换为 `{参数名}`
+pub fn route_pattern(req: &HttpRequest) -> Option<String> {
+    let path = req.path();
+    if !req.resource_map().has_resource(path) {
+        return None;
+    }
+
+    let mut segments = path.split('/').map(String::from).collect::<Vec<_>>();
+    let mut pos = 0;
+    for (name, value) in req.match_info().iter() {
+        if value.is_empty() {
+            continue;
+        }
+        let placeholder = format!("{{{}}}", name);
+        if value.contains('/') {
+            // 匹配多段的参数，如 {tail:.*}
+            if let Some(i) = (pos..segments.len()).find(|i| segments[*i..].join("/") == value) {
+                segments.truncate(i);
+                segments.push(placeholder);
+                break;
+            }
+        } else if let Some(i) = (pos..segments.len()).find(|i| segments[*i] == value) {
+            segments[i] = placeholder;
+            pos = i + 1;
+        }
+    }
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn test_route_pattern() {
+        let mut app = test::init_service(
+            App::new()
+                .service(
+                    web::scope("/users/{id}")
+                        .route("/posts/{post_id}", web::get().to(|req: HttpRequest| {
+                            HttpResponse::Ok().body(route_pattern(&req).unwrap())
+                        }))
+                )
+                .route("/static/{tail:.*}", web::get().to(|req: HttpRequest| {
+                    HttpResponse::Ok().body(route_pattern(&req).unwrap())
+                }))
+                .default_service(web::to(|req: HttpRequest| {
+                    HttpResponse::NotFound().body(route_pattern(&req).unwrap_or_default())
+                }))
+        ).await;
+
+        let req = test::TestRequest::get().uri("/users/1/posts/1").to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "/users/{id}/posts/{post_id}");
+        let req = test::TestRequest::get().uri("/static/css/a.css").to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "/static/{tail}");
+        let req = test::TestRequest::get().uri("/nothing/here").to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "");
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        let guard = metrics.in_flight("GET");
+        metrics.observe("GET", "/users/{id}", 200, Duration::from_millis(20));
+        metrics.observe("GET", "/users/{id}", 200, Duration::from_secs(20));
+        metrics.ws_connected();
+
+        let mut out = String::new();
+        metrics.render_prometheus(&mut out);
+        let labels = r#"method="GET",route="/users/{id}",status="200""#;
+        assert!(out.contains(&format!("http_requests_total{{{}}} 2\n", labels)));
+        assert!(out.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"0.01\"}} 0\n", labels)));
+        assert!(out.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1\n", labels)));
+        assert!(out.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"10\"}} 1\n", labels)));
+        assert!(out.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n", labels)));
+        assert!(out.contains("http_requests_in_flight{method=\"GET\"} 1\n"));
+        assert!(out.contains("websocket_connections 1\n"));
+
+        drop(guard);
+        let mut out = String::new();
+        metrics.render_prometheus(&mut out);
+        assert!(out.contains("http_requests_in_flight{method=\"GET\"} 0\n"));
+    }
+}
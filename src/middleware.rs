@@ -0,0 +1,1028 @@
+//! 中间件
+
+use actix_service::{Service, Transform};
+use actix_session::{Session, SessionStatus};
+use actix_web::cookie::Cookie;
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
+use actix_web::dev::{Body, BodySize, MessageBody, Payload, ResponseBody};
+use actix_web::http::header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use actix_web::http::HeaderMap;
+use actix_web::web::Bytes;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{ok, Ready};
+use futures::StreamExt;
+// use futures::Future;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::logging::{AccessEntry, ACCESS_LOG_TARGET};
+use crate::metrics::{self, Metrics};
+use crate::settings::LogFormat;
+use crate::authz::{Principal, Require};
+use crate::handlers::db_conn;
+use crate::handlers::viewer::current_user_id;
+use crate::session::{self, SessionCookie, SessionState, Sessions, StoredSession};
+use crate::token::{self, BearerIdentity, Jwt, TokenKind};
+use crate::trace::{BodyCapture, TraceRecord, Tracer};
+use crate::{AppError, PoolConnection, ResponseWrapper};
+
+// 1. Middleware initialization, middleware factory gets called with next service in chain as parameter.
+// 2. Middleware's call method gets called with normal request.
+
+// 中间件处理分为两个步骤。
+// 1. 中间件初始化，使用链中的下一个服务作为参数调用中间件工厂。
+// 2. 中间件的call方法被普通请求调用。 
+//
+// SayHi 记录请求和响应的详细信息（调试用），配置见 `crate::trace`
+// 需要注册在 RequestIdMiddleware 内层（之前 wrap），才能记录请求 ID
+pub struct SayHi {
+    tracer: Tracer,
+}
+
+impl SayHi {
+    pub fn new(tracer: Tracer) -> Self {
+        SayHi { tracer }
+    }
+}
+
+// 中间件工厂需要实现 `Transform` 来自 `actix-service` crate
+// Transform 特质相当于如下函数声明（忽略错误）
+// type Service = async fn<Req, Res, Err>(req: Req) -> Result<Res, Err>
+// async fn new_transform<NextReq, NextRes, NextErr, Req, Res, Err, InitErr>(next_service: Service<NextReq, NextRes, NextErr>) -> Result<Service<Req, Res, Err>, InitErr>
+
+// `S` - 下一个 service 的 类型
+// `B` - response body 的类型
+impl<S, B> Transform<S> for SayHi
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    // 当前 Service 的请求
+    type Request = ServiceRequest;
+    // 当前 Service 的响应，包装了 body 以便复制响应体
+    type Response = ServiceResponse<TracedBody<B>>;
+    // 当前 Service 的错误类型
+    type Error = Error;
+    // 创建 当前 Service 时可能出现的错误
+    type InitError = ();
+    // 当前 Transform 的类型
+    type Transform = SayHiMiddleware<S>;
+    // 异步的包装
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    // 工厂方法
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(SayHiMiddleware { service, tracer: self.tracer.clone() })
+    }
+}
+
+pub struct SayHiMiddleware<S> {
+    service: S,
+    tracer: Tracer,
+}
+
+// Service 中间件/服务，基本等价于
+// 一个异步处理函数：async fn<Req, Res, Err>(req: Req) -> Result<Res, Err>
+// 一个异步就绪判断：async fn poll_ready<Err>() -> Result<(), Err>
+// 服务是 Actix-web 的核心抽象
+impl<S, B> Service for SayHiMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<TracedBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    // 一个异步函数
+    // 确定当前 Service 是否可以处理请求，不可以处理时，返回 Pending
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    // 处理函数，不应该调用 poll_ready。允许
+    // actix可能在不调用poll_ready的情况下调用call，因此实现上必须要考虑这一点
+    fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        if !self.tracer.should_trace(req.path()) {
+            let fut = self.service.call(req);
+            return Box::pin(async move {
+                Ok(fut.await?.map_body(|_, body| ResponseBody::Body(TracedBody { body, capture: None })))
+            });
+        }
+
+        let start = Instant::now();
+        let tracer = self.tracer.clone();
+        let capture_headers = tracer.settings().capture_headers;
+        let capture_body = tracer.settings().capture_body;
+        let max_body_size = tracer.settings().max_body_size;
+
+        let mut record = TraceRecord::new(req.method().as_str(), req.path(), &tracer.query(req.query_string()));
+        record.request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+        if capture_headers {
+            record.request_headers = Some(tracer.headers(req.headers()));
+        }
+
+        // 请求体在 handler 读取时复制，handler 没有读取的部分不会记录
+        let request_body = if capture_body {
+            let capture = Rc::new(RefCell::new(BodyCapture::new(max_body_size)));
+            let content_type = content_type(req.headers());
+            let payload = {
+                let capture = capture.clone();
+                req.take_payload().inspect(move |chunk| {
+                    if let Ok(chunk) = chunk {
+                        capture.borrow_mut().push(chunk);
+                    }
+                })
+            };
+            req.set_payload(Payload::Stream(Box::pin(payload)));
+            Some((capture, content_type))
+        } else {
+            None
+        };
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await;
+            record.latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+            if let Some((capture, content_type)) = request_body {
+                record.request_body = Some(tracer.body(&capture.borrow(), content_type.as_deref()));
+            }
+
+            let res = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    record.status = e.as_response_error().status_code().as_u16();
+                    tracer.record(record);
+                    return Err(e);
+                }
+            };
+            record.status = res.status().as_u16();
+            if capture_headers {
+                record.response_headers = Some(tracer.headers(res.headers()));
+            }
+
+            // 需要记录响应体时，在响应体发送完（或连接断开）后再输出
+            let capture = if capture_body {
+                Some(ResponseCapture {
+                    content_type: content_type(res.headers()),
+                    body: BodyCapture::new(max_body_size),
+                    tracer,
+                    record,
+                })
+            } else {
+                tracer.record(record);
+                None
+            };
+            Ok(res.map_body(|_, body| ResponseBody::Body(TracedBody { body, capture })))
+        })
+    }
+}
+
+fn content_type(headers: &HeaderMap) -> Option<String> {
+    headers.get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+struct ResponseCapture {
+    tracer: Tracer,
+    record: TraceRecord,
+    body: BodyCapture,
+    content_type: Option<String>,
+}
+
+/// 复制流经的响应体，drop 时输出追踪记录
+pub struct TracedBody<B> {
+    body: ResponseBody<B>,
+    capture: Option<ResponseCapture>,
+}
+
+impl<B: MessageBody> MessageBody for TracedBody<B> {
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Error>>> {
+        let poll = self.body.poll_next(cx);
+        if let (Poll::Ready(Some(Ok(chunk))), Some(capture)) = (&poll, &mut self.capture) {
+            capture.body.push(chunk);
+        }
+        poll
+    }
+}
+
+impl<B> Drop for TracedBody<B> {
+    fn drop(&mut self) {
+        if let Some(mut capture) = self.capture.take() {
+            let body = capture.tracer.body(&capture.body, capture.content_type.as_deref());
+            capture.record.response_body = Some(body);
+            capture.tracer.record(capture.record);
+        }
+    }
+}
+
+/// 记录请求数、耗时和进行中的请求数，见 `crate::metrics`
+///
+/// 需要作为最外层的中间件注册（最后一个 wrap），这样耗时包含其他中间件
+pub struct RequestMetrics {
+    metrics: Metrics,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Metrics) -> Self {
+        RequestMetrics { metrics }
+    }
+}
+
+impl<S, B> Transform<S> for RequestMetrics
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsMiddleware { service, metrics: self.metrics.clone() })
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    metrics: Metrics,
+}
+
+impl<S, B> Service for RequestMetricsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let method = req.method().to_string();
+        let metrics = self.metrics.clone();
+        // 路由之前还不知道路由模板，进行中的请求只按 method 统计
+        let in_flight = metrics.in_flight(&method);
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await;
+            drop(in_flight);
+            match &res {
+                // 路由之后 match_info 中才有路径参数
+                Ok(res) => {
+                    let route = metrics::route_pattern(res.request());
+                    let route = route.as_deref().unwrap_or(metrics::UNMATCHED_ROUTE);
+                    metrics.observe(&method, route, res.status().as_u16(), start.elapsed());
+                }
+                // handler 的错误已经转换为响应，这里只有内层中间件返回的错误，拿不到请求
+                Err(e) => {
+                    let status = e.as_response_error().status_code().as_u16();
+                    metrics.observe(&method, "unknown", status, start.elapsed());
+                }
+            }
+            res
+        })
+    }
+}
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 客户端传入的请求 ID 最大长度，超过时重新生成
+pub const REQUEST_ID_MAX_LEN: usize = 128;
+
+/// 当前请求的 ID，由 `RequestIdMiddleware` 写入 extensions，handler 中可以直接提取
+///
+/// ```ignore
+/// async fn handler(request_id: RequestId) -> String {
+///     request_id.0
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// 32 位十六进制的随机 ID
+    pub fn generate() -> Self {
+        RequestId(format!("{:032x}", rand::random::<u128>()))
+    }
+
+    /// 只接受可见 ASCII 字符，避免日志注入
+    pub fn is_valid(id: &str) -> bool {
+        !id.is_empty() && id.len() <= REQUEST_ID_MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+    }
+
+    pub fn of(req: &HttpRequest) -> Option<RequestId> {
+        req.extensions().get::<RequestId>().cloned()
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    // 没有注册中间件时生成一个新的 ID
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ok(RequestId::of(req).unwrap_or_else(RequestId::generate))
+    }
+}
+
+/// 读取或生成 X-Request-Id，写入请求 extensions 和响应头
+///
+/// `AppError` 的响应在 `ResponseError::error_response` 中生成，拿不到请求，
+/// 这里重新生成响应体，补上 `request_id` 字段
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S> for RequestIdMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdService { service })
+    }
+}
+
+pub struct RequestIdService<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestIdService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let id = req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| RequestId::is_valid(v))
+            .map(|v| RequestId(v.to_string()))
+            .unwrap_or_else(RequestId::generate);
+        req.extensions_mut().insert(id.clone());
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+
+            let body = res.response().error()
+                .and_then(|e| e.as_error::<AppError>())
+                .map(|e| ResponseWrapper::<()>::from(e).request_id(id.0.clone()))
+                .and_then(|body| serde_json::to_string(&body).ok());
+            if let Some(body) = body {
+                res = res.map_body(|_, _| ResponseBody::Other(Body::from(body)));
+            }
+
+            // is_valid 保证了可以转换为 HeaderValue
+            if let Ok(value) = HeaderValue::from_str(&id.0) {
+                res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// 访问日志，格式由 `log.format` 决定，见 `crate::logging`
+///
+/// 需要注册在 `RequestIdMiddleware` 外层（之后 wrap），才能记录请求 ID
+pub struct AccessLog {
+    format: LogFormat,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat) -> Self {
+        AccessLog { format }
+    }
+}
+
+impl<S, B> Transform<S> for AccessLog
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessLogMiddleware { service, format: self.format })
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+    format: LogFormat,
+}
+
+impl<S, B> Service for AccessLogMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let format = self.format;
+        let mut entry = AccessEntry {
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            route: "unknown".to_string(),
+            status: 0,
+            latency_ms: 0.0,
+            bytes: None,
+            request_id: None,
+            peer: req.peer_addr().map(|addr| addr.ip().to_string()),
+        };
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await;
+            entry.latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+            match &res {
+                Ok(res) => {
+                    let req = res.request();
+                    entry.route = metrics::route_pattern(req)
+                        .unwrap_or_else(|| metrics::UNMATCHED_ROUTE.to_string());
+                    entry.status = res.status().as_u16();
+                    entry.request_id = RequestId::of(req).map(|id| id.0);
+                    entry.bytes = match res.response().body().size() {
+                        BodySize::Empty | BodySize::None => Some(0),
+                        BodySize::Sized(n) => Some(n as u64),
+                        BodySize::Sized64(n) => Some(n),
+                        BodySize::Stream => None,
+                    };
+                }
+                Err(e) => entry.status = e.as_response_error().status_code().as_u16(),
+            }
+            log::info!(target: ACCESS_LOG_TARGET, "{}", entry.render(format));
+            res
+        })
+    }
+}
+
+/// 验证 `Authorization: Bearer` 请求头中的 access token，见 `crate::token`
+///
+/// 验证通过时把 `BearerIdentity` 写入请求 extensions，`handlers::viewer::CurrentUser` 优先使用它；
+/// 没有 Bearer token 时直接放行（可能使用 cookie session），token 无效、过期或已撤销时返回 401。
+/// 需要注册在 `RequestIdMiddleware` 内层（之前 wrap），错误响应中才有请求 ID
+pub struct BearerAuth {
+    jwt: Jwt,
+    pool: PoolConnection,
+}
+
+impl BearerAuth {
+    pub fn new(jwt: Jwt, pool: PoolConnection) -> Self {
+        BearerAuth { jwt, pool }
+    }
+}
+
+impl<S, B> Transform<S> for BearerAuth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BearerAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(BearerAuthMiddleware {
+            // 验证 token 之后才调用下一个 service，需要在 future 中持有
+            service: Rc::new(RefCell::new(service)),
+            jwt: self.jwt.clone(),
+            pool: self.pool.clone(),
+        })
+    }
+}
+
+pub struct BearerAuthMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    jwt: Jwt,
+    pool: PoolConnection,
+}
+
+impl<S, B> Service for BearerAuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let token = match bearer_token(req.headers()) {
+            Some(token) => token.to_string(),
+            None => return Box::pin(self.service.borrow_mut().call(req)),
+        };
+        let service = self.service.clone();
+        let jwt = self.jwt.clone();
+        let pool = self.pool.clone();
+
+        Box::pin(async move {
+            match authenticate(&jwt, &pool, &token).await {
+                Ok(identity) => {
+                    req.extensions_mut().insert(identity);
+                    let fut = service.borrow_mut().call(req);
+                    fut.await
+                }
+                Err(e) => Ok(req.error_response(e).map_body(|_, body| body.into_body())),
+            }
+        })
+    }
+}
+
+// 其他认证方式的 Authorization 请求头返回 None
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_at(value.find(' ').unwrap_or(value.len()));
+    if scheme.eq_ignore_ascii_case("bearer") {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
+
+async fn authenticate(jwt: &Jwt, pool: &PoolConnection, token: &str) -> Result<BearerIdentity, AppError> {
+    let claims = jwt.decode(token, TokenKind::Access)?;
+    let user_id = claims.user_id()?;
+    let conn = db_conn(pool).await?;
+    let jti = claims.jti.clone();
+    if web::block(move || token::is_revoked(&conn, &jti)).await? {
+        return Err(AppError::Unauthorized("token revoked".to_string()));
+    }
+    Ok(BearerIdentity { user_id, claims })
+}
+
+/// 加载当前访问者的角色和权限，检查是否满足要求，见 `crate::authz`
+///
+/// 可以注册在 scope 或 resource 上（`wrap`），未登录返回 401，不满足要求返回 403。
+/// 加载的 `Principal` 写入请求 extensions，内层的 `authz::Require` guard 和 handler 可以直接使用。
+/// 需要注册在 `BearerAuth` 内层，数据库连接池从 app data 读取
+pub struct Authorize {
+    require: Option<Require>,
+}
+
+impl Authorize {
+    pub fn new(require: Require) -> Self {
+        Authorize { require: Some(require) }
+    }
+
+    /// 只加载，不检查（未登录也可以访问），供内层的 guard 使用
+    pub fn load() -> Self {
+        Authorize { require: None }
+    }
+}
+
+impl<S, B> Transform<S> for Authorize
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthorizeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthorizeMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            require: Rc::new(self.require.clone()),
+        })
+    }
+}
+
+pub struct AuthorizeMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    require: Rc<Option<Require>>,
+}
+
+impl<S, B> Service for AuthorizeMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let require = self.require.clone();
+        // 外层的 Authorize 已经加载过时不再查询
+        let loaded = req.extensions().get::<Principal>().cloned();
+        let user_id = current_user_id(&req);
+        let pool = req.app_data::<PoolConnection>();
+
+        Box::pin(async move {
+            let principal = match (loaded, pool) {
+                (Some(principal), _) => Ok(principal),
+                (None, Some(pool)) => Principal::fetch(&pool, user_id).await,
+                (None, None) => Err(AppError::internal("PoolConnection is not registered")),
+            };
+            let checked = principal.and_then(|principal| {
+                if let Some(require) = require.as_ref() {
+                    principal.ensure(require)?;
+                }
+                Ok(principal)
+            });
+            match checked {
+                Ok(principal) => {
+                    req.extensions_mut().insert(principal);
+                    let fut = service.borrow_mut().call(req);
+                    fut.await
+                }
+                Err(e) => Ok(req.error_response(e).map_body(|_, body| body.into_body())),
+            }
+        })
+    }
+}
+
+/// 服务端 session，替代 actix-session 的 `CookieSession`，见 `crate::session`
+///
+/// 请求前根据 cookie 中的 session id 从存储中加载数据，handler 通过 `actix_session::Session` 读写；
+/// 响应时按 session 的状态保存、更换 id 或删除，并设置 cookie。
+/// 存储读取失败时当作没有 session，保存失败时返回 500
+pub struct ServerSession {
+    sessions: Sessions,
+}
+
+impl ServerSession {
+    pub fn new(sessions: Sessions) -> Self {
+        ServerSession { sessions }
+    }
+}
+
+impl<S, B> Transform<S> for ServerSession
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ServerSessionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ServerSessionMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            sessions: self.sessions.clone(),
+        })
+    }
+}
+
+pub struct ServerSessionMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    sessions: Sessions,
+}
+
+impl<S, B> Service for ServerSessionMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let sessions = self.sessions.clone();
+        let cookie = req.cookie(sessions.cookie_name()).map(|cookie| sessions.verify(&cookie));
+
+        Box::pin(async move {
+            let mut loaded = None;
+            if let Some(Some(cookie)) = &cookie {
+                match sessions.store().load(cookie.id.clone()).await {
+                    Ok(Some(stored)) => {
+                        Session::set_session(stored.state.clone().into_iter(), &mut req);
+                        loaded = Some((cookie.clone(), stored));
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::warn!("failed to load session: {}", e),
+                }
+            }
+
+            let fut = service.borrow_mut().call(req);
+            let mut res = fut.await?;
+            let (status, state) = Session::get_changes(&mut res);
+            let state: SessionState = state.map(|state| state.collect()).unwrap_or_default();
+            let set_cookie = commit_session(&sessions, cookie.is_some(), loaded, status, state).await?;
+            if let Some(set_cookie) = set_cookie {
+                res.response_mut().add_cookie(&set_cookie)?;
+            }
+            Ok(res)
+        })
+    }
+}
+
+// 按 session 的状态更新存储，返回需要设置的 cookie
+async fn commit_session(
+    sessions: &Sessions,
+    has_cookie: bool,
+    loaded: Option<(SessionCookie, StoredSession)>,
+    status: SessionStatus,
+    state: SessionState,
+) -> Result<Option<Cookie<'static>>, AppError> {
+    let store = sessions.store();
+    let id = match (loaded, status) {
+        // 没有修改时只延长有效期，或者使用当前密钥重新签名
+        (Some((cookie, stored)), SessionStatus::Unchanged) => {
+            if sessions.needs_refresh(&stored) {
+                store.save(cookie.id.clone(), StoredSession { state, expires_at: sessions.expires_at() }).await?;
+                return Ok(Some(sessions.cookie(&cookie.id)));
+            }
+            return Ok(if cookie.outdated { Some(sessions.cookie(&cookie.id)) } else { None });
+        }
+        // cookie 无效或 session 已经过期，让浏览器删除
+        (None, SessionStatus::Unchanged) => {
+            return Ok(if has_cookie { Some(sessions.removal_cookie()) } else { None });
+        }
+        // 删除旧 id，renew 时数据保存到新 id
+        (Some((cookie, _)), SessionStatus::Renewed) | (Some((cookie, _)), SessionStatus::Purged) => {
+            store.remove(cookie.id).await?;
+            None
+        }
+        (loaded, _) => loaded.map(|(cookie, _)| cookie.id),
+    };
+
+    if state.is_empty() {
+        if let Some(id) = id {
+            store.remove(id).await?;
+        }
+        return Ok(if has_cookie { Some(sessions.removal_cookie()) } else { None });
+    }
+    let id = id.unwrap_or_else(session::new_session_id);
+    store.save(id.clone(), StoredSession { state, expires_at: sessions.expires_at() }).await?;
+    Ok(Some(sessions.cookie(&id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn test_request_metrics() {
+        let metrics = Metrics::new();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequestMetrics::new(metrics.clone()))
+                .route("/users/{id}", web::get().to(HttpResponse::Ok))
+        ).await;
+
+        for uri in &["/users/1", "/users/2", "/nothing"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            test::call_service(&mut app, req).await;
+        }
+
+        let mut out = String::new();
+        metrics.render_prometheus(&mut out);
+        assert!(out.contains(r#"http_requests_total{method="GET",route="/users/{id}",status="200"} 2"#));
+        assert!(out.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#));
+        assert!(out.contains(r#"http_requests_in_flight{method="GET"} 0"#));
+    }
+
+    #[actix_rt::test]
+    async fn test_say_hi() {
+        let tracer = Tracer::new(crate::settings::TraceSettings {
+            enabled: true,
+            max_body_size: 16,
+            ..Default::default()
+        });
+        let mut app = test::init_service(
+            App::new()
+                .wrap(SayHi::new(tracer.clone()))
+                .wrap(RequestIdMiddleware)
+                .route("/echo", web::post().to(|body: web::Bytes| {
+                    HttpResponse::Ok().content_type("application/json").body(body)
+                }))
+                .route("/debug/requests", web::get().to(HttpResponse::Ok))
+        ).await;
+
+        let req = test::TestRequest::post().uri("/echo?a=1")
+            .header("X-Request-Id", "req-1")
+            .header("Authorization", "Bearer abc")
+            .header("Content-Type", "application/json")
+            .set_payload(r#"{"password":"x","name":"xiaoming"}"#)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(test::read_body(resp).await, r#"{"password":"x","name":"xiaoming"}"#);
+
+        // 排除的路径
+        let req = test::TestRequest::get().uri("/debug/requests").to_request();
+        test::call_service(&mut app, req).await;
+
+        let records = tracer.recent(10);
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.request_id.as_deref(), Some("req-1"));
+        assert_eq!(record.query, "a=1");
+        assert_eq!(record.status, 200);
+        assert_eq!(record.request_headers.as_ref().unwrap()["authorization"], "***");
+        let body = record.request_body.as_ref().unwrap();
+        assert_eq!((body.size, body.truncated), (34, true));
+        assert_eq!(body.content, r#"{"password":"***","#);
+        assert_eq!(record.response_body.as_ref().unwrap().content, r#"{"password":"***","#);
+    }
+
+    #[actix_rt::test]
+    async fn test_request_id() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware)
+                .route("/id", web::get().to(|id: RequestId| async move { id.0 }))
+                .route("/error", web::get().to(|| async { Err::<HttpResponse, _>(AppError::not_found()) }))
+        ).await;
+
+        // 使用客户端传入的 ID
+        let req = test::TestRequest::get().uri("/id").header("X-Request-Id", "req-1").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get("X-Request-Id").unwrap(), "req-1");
+        assert_eq!(test::read_body(resp).await, "req-1");
+
+        // 不合法时重新生成
+        let req = test::TestRequest::get().uri("/id").header("X-Request-Id", "a b").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let id = resp.headers().get("X-Request-Id").unwrap().to_str().unwrap().to_string();
+        assert_eq!(id.len(), 32);
+        assert_eq!(test::read_body(resp).await, id.as_str());
+
+        // 错误响应中也带有 request_id
+        let req = test::TestRequest::get().uri("/error").header("X-Request-Id", "req-2").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["request_id"], "req-2");
+        assert_eq!(body["code"], 40400);
+    }
+
+    #[actix_rt::test]
+    async fn test_server_session() {
+        use crate::session::{MemoryStore, SessionStore};
+        use crate::settings::SessionSettings;
+        use actix_session::Session;
+        use std::sync::Arc;
+
+        let key = |c: &str| c.repeat(32);
+        let store = Arc::new(MemoryStore::new());
+        let old = Sessions::new(&SessionSettings { keys: vec![key("a")], ..SessionSettings::default() }, store.clone());
+        // 轮换密钥：新密钥签名，旧密钥只用于验证
+        let rotated = Sessions::new(&SessionSettings { keys: vec![key("b"), key("a")], ..SessionSettings::default() }, store.clone());
+        let app = |sessions: Sessions| App::new()
+            .wrap(ServerSession::new(sessions))
+            .route("/get", web::get().to(|session: Session| async move {
+                session.get::<i32>("n").unwrap().map(|n| n.to_string()).unwrap_or_default()
+            }))
+            .route("/set", web::get().to(|session: Session| async move {
+                session.set("n", 1).unwrap();
+                ""
+            }))
+            .route("/renew", web::get().to(|session: Session| async move {
+                session.renew();
+                ""
+            }))
+            .route("/purge", web::get().to(|session: Session| async move {
+                session.purge();
+                ""
+            }));
+        let mut app_old = test::init_service(app(old.clone())).await;
+        let mut app_rotated = test::init_service(app(rotated.clone())).await;
+
+        // 没有修改时不设置 cookie
+        let resp = test::call_service(&mut app_old, test::TestRequest::with_uri("/get").to_request()).await;
+        assert!(resp.response().cookies().next().is_none());
+
+        let resp = test::call_service(&mut app_old, test::TestRequest::with_uri("/set").to_request()).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(actix_web::cookie::SameSite::Lax));
+        assert_eq!(store.len(), 1);
+        // cookie 中只有签名的 session id
+        let id = old.verify(&cookie).unwrap().id;
+        assert!(!cookie.value().contains("\"n\""));
+
+        // 旧密钥签名的 cookie 仍然有效，并使用新密钥重新签名
+        let req = test::TestRequest::with_uri("/get").cookie(cookie.clone()).to_request();
+        let resp = test::call_service(&mut app_rotated, req).await;
+        let resigned = resp.response().cookies().next().unwrap().into_owned();
+        assert_eq!(test::read_body(resp).await, "1");
+        assert_eq!(rotated.verify(&resigned).unwrap(), SessionCookie { id: id.clone(), outdated: false });
+        assert!(old.verify(&resigned).is_none());
+
+        // renew 更换 id，数据保留，旧 id 失效
+        let req = test::TestRequest::with_uri("/renew").cookie(resigned.clone()).to_request();
+        let resp = test::call_service(&mut app_rotated, req).await;
+        let renewed = resp.response().cookies().next().unwrap().into_owned();
+        assert_ne!(rotated.verify(&renewed).unwrap().id, id);
+        assert_eq!(store.len(), 1);
+        let req = test::TestRequest::with_uri("/get").cookie(resigned).to_request();
+        let resp = test::call_service(&mut app_rotated, req).await;
+        // 无效的 cookie 会被删除
+        assert_eq!(resp.response().cookies().next().unwrap().max_age().map(|d| d.num_seconds()), Some(0));
+        assert_eq!(test::read_body(resp).await, "");
+
+        // 剩余有效期不足一半时延长
+        let renewed_id = rotated.verify(&renewed).unwrap().id;
+        let mut stored = store.load(renewed_id.clone()).await.unwrap().unwrap();
+        stored.expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(60);
+        store.save(renewed_id.clone(), stored).await.unwrap();
+        let req = test::TestRequest::with_uri("/get").cookie(renewed.clone()).to_request();
+        let resp = test::call_service(&mut app_rotated, req).await;
+        assert!(resp.response().cookies().next().is_some());
+        assert!(!rotated.needs_refresh(&store.load(renewed_id).await.unwrap().unwrap()));
+
+        let req = test::TestRequest::with_uri("/purge").cookie(renewed.clone()).to_request();
+        let resp = test::call_service(&mut app_rotated, req).await;
+        assert_eq!(resp.response().cookies().next().unwrap().value(), "");
+        assert!(store.is_empty());
+        let req = test::TestRequest::with_uri("/get").cookie(renewed).to_request();
+        assert_eq!(test::read_response(&mut app_rotated, req).await, "");
+    }
+}
@@ -0,0 +1,212 @@
+//! 编译期嵌入的数据库迁移
+//!
+//! 迁移 SQL 通过 `include_str!` 打包进二进制，每个数据库后端使用 `migrations/<backend>` 下
+//! 同名的迁移目录。新增迁移时需要在 `embedded` 中登记目录名。
+
+use diesel::connection::SimpleConnection;
+use diesel::migration::{Migration, RunMigrationsError};
+use diesel::prelude::*;
+use migrations_internals::MigrationConnection;
+use std::fmt;
+use std::io::Write;
+
+use crate::DbConnection;
+
+#[cfg(feature = "mysql")]
+macro_rules! migration_dir { () => { "../migrations/mysql" } }
+#[cfg(feature = "sqlite")]
+macro_rules! migration_dir { () => { "../migrations/sqlite" } }
+#[cfg(feature = "postgres")]
+macro_rules! migration_dir { () => { "../migrations/postgres" } }
+
+macro_rules! embed {
+    ($($name:literal),* $(,)?) => {
+        vec![$(
+            EmbeddedMigration::new(
+                $name,
+                include_str!(concat!(migration_dir!(), "/", $name, "/up.sql")),
+                include_str!(concat!(migration_dir!(), "/", $name, "/down.sql")),
+            )
+        ),*]
+    };
+}
+
+/// 嵌入二进制的所有迁移，按版本号升序
+pub fn embedded() -> Vec<EmbeddedMigration> {
+    embed![
+        "2020-03-04-090155_init",
+        "2020-03-12-083000_users_name_unique",
+        "2020-03-20-120000_counters",
+        "2020-03-25-120000_users_password",
+        "2020-03-28-120000_revoked_tokens",
+        "2020-04-02-120000_roles",
+        "2020-04-05-120000_sessions",
+    ]
+}
+
+// diesel 记录已执行迁移的表，由 setup_database 创建
+table! {
+    __diesel_schema_migrations (version) {
+        version -> VarChar,
+        run_on -> Timestamp,
+    }
+}
+
+pub struct EmbeddedMigration {
+    name: &'static str,
+    version: String,
+    up: &'static str,
+    down: &'static str,
+}
+
+impl EmbeddedMigration {
+    fn new(name: &'static str, up: &'static str, down: &'static str) -> Self {
+        // 与 diesel cli 一致：目录名 `_` 之前的部分去掉 `-` 即为版本号
+        let version = name.split('_').next().unwrap_or(name).replace('-', "");
+        EmbeddedMigration { name, version, up, down }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl Migration for EmbeddedMigration {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn run(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.up).map_err(Into::into)
+    }
+
+    fn revert(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.down).map_err(Into::into)
+    }
+}
+
+impl fmt::Debug for EmbeddedMigration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// 数据库中尚未执行的迁移
+pub fn pending(conn: &DbConnection) -> Result<Vec<EmbeddedMigration>, RunMigrationsError> {
+    migrations_internals::setup_database(conn)?;
+    let already_run = conn.previously_run_migration_versions()?;
+    Ok(embedded()
+        .into_iter()
+        .filter(|m| !already_run.contains(m.version()))
+        .collect())
+}
+
+/// 执行所有未执行的迁移，返回执行过的迁移
+pub fn run_pending(conn: &DbConnection, out: &mut dyn Write) -> Result<Vec<EmbeddedMigration>, RunMigrationsError> {
+    let pending = pending(conn)?;
+    for m in &pending {
+        run_one(conn, m, out)?;
+    }
+    Ok(pending)
+}
+
+/// 回滚最近执行的一个迁移，没有执行过任何迁移时返回 None
+pub fn revert_latest(conn: &DbConnection, out: &mut dyn Write) -> Result<Option<EmbeddedMigration>, RunMigrationsError> {
+    migrations_internals::setup_database(conn)?;
+    let latest = match conn.latest_run_migration_version()? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let migration = embedded()
+        .into_iter()
+        .find(|m| m.version() == latest)
+        .ok_or(migrations_internals::MigrationError::UnknownMigrationVersion(latest))?;
+
+    conn.transaction(|| {
+        writeln!(out, "Rolling back migration {}", migration.name)?;
+        migration.revert(conn)?;
+        diesel::delete(__diesel_schema_migrations::table)
+            .filter(__diesel_schema_migrations::version.eq(migration.version()))
+            .execute(conn)?;
+        Ok::<_, RunMigrationsError>(())
+    })?;
+    Ok(Some(migration))
+}
+
+/// 回滚并重新执行最近的一个迁移
+pub fn redo_latest(conn: &DbConnection, out: &mut dyn Write) -> Result<Option<EmbeddedMigration>, RunMigrationsError> {
+    let migration = revert_latest(conn, out)?;
+    if let Some(m) = &migration {
+        run_one(conn, m, out)?;
+    }
+    Ok(migration)
+}
+
+fn run_one(conn: &DbConnection, migration: &EmbeddedMigration, out: &mut dyn Write) -> Result<(), RunMigrationsError> {
+    conn.transaction(|| {
+        writeln!(out, "Running migration {}", migration.name)?;
+        migration.run(conn)?;
+        conn.insert_new_migration(migration.version())?;
+        Ok(())
+    })
+}
+
+/// 数据库结构落后于二进制时拒绝启动
+#[derive(Debug)]
+pub enum SchemaError {
+    Query(RunMigrationsError),
+    Behind(Vec<String>),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Query(e) => write!(f, "failed to check schema version: {}", e),
+            SchemaError::Behind(pending) => write!(
+                f,
+                "database schema is behind, pending migrations: {} (run `migrate run`)",
+                pending.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+pub fn ensure_up_to_date(conn: &DbConnection) -> Result<(), SchemaError> {
+    let pending = pending(conn).map_err(SchemaError::Query)?;
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaError::Behind(pending.iter().map(|m| m.name.to_string()).collect()))
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_revert_redo() {
+        let conn = DbConnection::establish(":memory:").unwrap();
+        let mut out = Vec::new();
+        let all = embedded().len();
+
+        assert_eq!(pending(&conn).unwrap().len(), all);
+        assert!(ensure_up_to_date(&conn).is_err());
+
+        assert_eq!(run_pending(&conn, &mut out).unwrap().len(), all);
+        assert!(pending(&conn).unwrap().is_empty());
+        assert!(ensure_up_to_date(&conn).is_ok());
+
+        let reverted = revert_latest(&conn, &mut out).unwrap().unwrap();
+        let pending_now = pending(&conn).unwrap();
+        assert_eq!(pending_now.len(), 1);
+        assert_eq!(pending_now[0].name(), reverted.name());
+
+        run_pending(&conn, &mut out).unwrap();
+        let redone = redo_latest(&conn, &mut out).unwrap().unwrap();
+        assert_eq!(redone.name(), reverted.name());
+        assert!(pending(&conn).unwrap().is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use chrono::NaiveDateTime;
-use crate::schema::{posts, users};
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::schema::{counters, posts, revoked_tokens, sessions, user_roles, users};
 
-#[derive(Debug, Queryable, Insertable, Identifiable, AsChangeset, Associations)]
+#[derive(Debug, Clone, Queryable, Insertable, Identifiable, AsChangeset, Associations, Serialize)]
 #[belongs_to(User)]
 pub struct Post {
     pub id: i64,
@@ -11,36 +12,133 @@ pub struct Post {
     pub published: bool,
 }
 
-#[derive(Debug, Queryable)]
+/// 新建文章，published 使用数据库默认值（未发布）
+#[derive(Debug, Insertable, Deserialize)]
+#[table_name="posts"]
+pub struct PostForInsert {
+    // user_id 来自路径参数，不从请求体中读取
+    #[serde(skip)]
+    pub user_id: i64,
+    pub title: String,
+    pub body: String,
+}
+
+/// 部分更新（PATCH），发布状态通过单独的接口修改
+#[derive(Debug, Identifiable, AsChangeset, Deserialize)]
+#[table_name="posts"]
+pub struct PostForUpdate {
+    #[serde(skip)]
+    pub id: i64,
+    pub title: Option<String>,
+    pub body: Option<String>,
+}
+
+impl PostForUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.body.is_none()
+    }
+}
+
+/// 用户及其文章，由 `grouped_by` 批量组装
+#[derive(Debug, Serialize)]
+pub struct UserWithPosts {
+    #[serde(flatten)]
+    pub user: User,
+    pub posts: Vec<Post>,
+}
+
+#[derive(Debug, Queryable, Identifiable, Serialize)]
 pub struct User {
     pub id: i64,
     pub name: String,
     pub hair_color: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// 不在响应中返回
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
 }
 
-#[derive(Debug, Insertable)]
+#[derive(Debug, Insertable, Deserialize)]
 #[table_name="users"]
 pub struct UserForInsert {
     pub name: String,
     pub hair_color: Option<String>,
+    // 由注册接口计算，不从请求体中读取
+    #[serde(skip)]
+    pub password_hash: Option<String>,
 }
 
-#[derive(Debug, Identifiable, AsChangeset)]
+/// 部分更新（PATCH），值为 None 的字段不会出现在 SET 子句中
+/// hair_color: 字段缺省 -> None（不修改）；null -> Some(None)（清空）；"xx" -> Some(Some("xx"))
+#[derive(Debug, Identifiable, AsChangeset, Deserialize)]
 #[table_name="users"]
 pub struct UserForUpdate {
+    // id 来自路径参数，不从请求体中读取
+    #[serde(skip)]
     pub id: i64,
-    pub name: String,
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
     pub hair_color: Option<Option<String>>,
 }
 
+impl UserForUpdate {
+    /// 是否没有任何需要更新的字段（diesel 不允许空的 SET 子句）
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.hair_color.is_none()
+    }
+}
+
 impl From<User> for UserForUpdate {
     fn from(user: User) -> Self {
         UserForUpdate {
             id: user.id,
-            name: user.name,
+            name: Some(user.name),
             hair_color: Some(user.hair_color),
         }
     }
 }
+
+/// 命名计数器，见 `crate::counter`
+#[derive(Debug, Clone, PartialEq, Queryable, Insertable, Identifiable, AsChangeset, Serialize)]
+#[primary_key(name)]
+#[table_name="counters"]
+pub struct Counter {
+    pub name: String,
+    pub value: i64,
+}
+
+/// 已撤销的 token，见 `crate::token`
+#[derive(Debug, Insertable)]
+#[table_name="revoked_tokens"]
+pub struct RevokedTokenForInsert {
+    pub jti: String,
+    pub user_id: i64,
+    pub expires_at: NaiveDateTime,
+}
+
+/// 服务端 session，data 为 JSON 对象，见 `crate::session`
+#[derive(Debug, Queryable, Insertable)]
+#[table_name="sessions"]
+pub struct SessionRecord {
+    pub id: String,
+    pub data: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// 用户的角色，见 `crate::authz`
+#[derive(Debug, Clone, PartialEq, Queryable, Insertable)]
+#[table_name="user_roles"]
+pub struct UserRole {
+    pub user_id: i64,
+    pub role: String,
+}
+
+// serde 默认会把 null 反序列化为外层的 None，这里让 null 变为 Some(None)
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
@@ -0,0 +1,66 @@
+//! 密码哈希
+//!
+//! 使用 Argon2id，结果为 PHC 格式字符串（包含参数和盐），存储在 `users.password_hash` 列。
+//! 计算较慢，需要在 `web::block` 中调用。
+
+use argon2::{Config, Variant, Version};
+
+use crate::AppError;
+
+/// 密码最小长度
+pub const PASSWORD_MIN_LEN: usize = 8;
+/// 密码最大长度，避免超长密码占用 CPU
+pub const PASSWORD_MAX_LEN: usize = 128;
+
+/// 用户不存在或没有密码时用来验证的哈希，参数与 `hash` 相同，使登录的耗时不会暴露用户名是否存在
+pub const DUMMY_HASH: &str = "$argon2id$v=19$m=4096,t=3,p=1$q920D5LrbQhD/DCSY/qzCQ$+Gq9eVglYCPcY51JwUYlQUNtU9ugD5hGwFWGg5TsDCA";
+
+fn config() -> Config<'static> {
+    Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        ..Config::default()
+    }
+}
+
+pub fn validate(password: &str) -> Result<(), AppError> {
+    let len = password.chars().count();
+    if !(PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&len) {
+        return Err(AppError::BadRequest(format!(
+            "password must be {}-{} characters",
+            PASSWORD_MIN_LEN, PASSWORD_MAX_LEN
+        )));
+    }
+    Ok(())
+}
+
+pub fn hash(password: &str) -> Result<String, AppError> {
+    let salt = rand::random::<[u8; 16]>();
+    argon2::hash_encoded(password.as_bytes(), &salt, &config()).map_err(AppError::internal)
+}
+
+/// 哈希格式错误时返回 false
+pub fn verify(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hashed = hash("correct horse").unwrap();
+        assert!(hashed.starts_with("$argon2id$"));
+        assert_ne!(hashed, hash("correct horse").unwrap());
+        assert!(verify(&hashed, "correct horse"));
+        assert!(!verify(&hashed, "wrong horse"));
+        assert!(!verify("not a hash", "correct horse"));
+        // 修改 config 后需要重新生成 DUMMY_HASH
+        let params = |h: &str| h.rsplitn(3, '$').nth(2).unwrap().to_string();
+        assert_eq!(params(DUMMY_HASH), params(&hashed));
+
+        assert!(validate("short").is_err());
+        assert!(validate("long enough").is_ok());
+    }
+}
@@ -0,0 +1,226 @@
+//! 统一的 JSON 响应格式
+
+use actix_web::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use actix_web::{Error, HttpRequest, HttpResponse, Responder};
+use futures::future::{ready, Ready};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use crate::middleware::{RequestId, REQUEST_ID_HEADER};
+
+/// 成功时的 code
+pub const CODE_SUCCESS: i32 = 0;
+
+// 自定义 Response
+// 所有接口统一返回该格式，HTTP 状态码和响应头不参与序列化
+#[derive(Serialize, Deserialize)]
+pub struct ResponseWrapper<T> {
+    /// 0 表示成功，其他值见 `AppError::code`
+    pub code: i32,
+    pub msg: String,
+    pub data: Option<T>,
+    // 列表接口的分页信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<PageInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(skip, default = "default_status")]
+    status: StatusCode,
+    #[serde(skip, default = "HeaderMap::new")]
+    headers: HeaderMap,
+}
+
+fn default_status() -> StatusCode {
+    StatusCode::OK
+}
+
+impl <T> ResponseWrapper<T> {
+    fn new(status: StatusCode, code: i32, msg: String, data: Option<T>) -> Self {
+        ResponseWrapper {
+            code,
+            msg,
+            data,
+            page: None,
+            request_id: None,
+            trace_id: None,
+            status,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// 200
+    pub fn ok(data: T) -> Self {
+        Self::new(StatusCode::OK, CODE_SUCCESS, "success".to_string(), Some(data))
+    }
+
+    /// 201
+    pub fn created(data: T) -> Self {
+        Self::new(StatusCode::CREATED, CODE_SUCCESS, "success".to_string(), Some(data))
+    }
+
+    /// 200，带分页信息
+    pub fn paged(data: T, page: PageInfo) -> Self {
+        Self::ok(data).page(page)
+    }
+
+    /// 错误响应，HTTP 状态码取 code 的前三位（如 40400 -> 404），无法识别时为 500
+    pub fn err(code: i32, msg: impl Into<String>) -> Self {
+        let status = u16::try_from(code / 100)
+            .ok()
+            .and_then(|s| StatusCode::from_u16(s).ok())
+            .filter(|s| s.is_client_error() || s.is_server_error())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Self::new(status, code, msg.into(), None)
+    }
+
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn get_status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// 设置响应头，非法的头会被忽略
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            self.headers.insert(name, value);
+        }
+        self
+    }
+
+    pub fn page(mut self, page: PageInfo) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+}
+
+impl ResponseWrapper<()> {
+    /// 204，没有响应体
+    pub fn no_content() -> Self {
+        Self::new(StatusCode::NO_CONTENT, CODE_SUCCESS, "success".to_string(), None)
+    }
+}
+
+impl <T> ResponseWrapper<T> where T: Serialize {
+    /// 构造 HttpResponse，序列化失败时返回 500
+    pub fn into_response(mut self, req: Option<&HttpRequest>) -> HttpResponse {
+        // 未显式设置时使用 RequestIdMiddleware 生成的 ID，没有注册中间件时使用请求头
+        if self.request_id.is_none() {
+            self.request_id = req.and_then(|req| {
+                RequestId::of(req).map(|id| id.0).or_else(|| {
+                    req.headers().get(REQUEST_ID_HEADER)
+                        .and_then(|v| v.to_str().ok())
+                        .map(String::from)
+                })
+            });
+        }
+
+        let mut builder = HttpResponse::build(self.status);
+        for (name, value) in self.headers.iter() {
+            builder.header(name.clone(), value.clone());
+        }
+        if self.status == StatusCode::NO_CONTENT {
+            return builder.finish();
+        }
+
+        match serde_json::to_string(&self) {
+            Ok(body) => builder
+                .content_type("application/json")
+                .body(body),
+            Err(e) => {
+                log::error!("failed to serialize response: {}", e);
+                let body = ResponseWrapper::<()>::err(50000, "internal server error");
+                HttpResponse::InternalServerError()
+                    .content_type("application/json")
+                    .body(serde_json::to_string(&body).unwrap_or_default())
+            }
+        }
+    }
+}
+
+// Responder
+impl <T> Responder for ResponseWrapper<T> where T: Serialize {
+    type Error = Error;
+    type Future = Ready<Result<HttpResponse, Error>>;
+
+    fn respond_to(self, req: &HttpRequest) -> Self::Future {
+        ready(Ok(self.into_response(Some(req))))
+    }
+}
+
+/// 分页信息，没有下一页时 next_cursor / next_offset 为空
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct PageInfo {
+    pub limit: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::{Body, ResponseBody};
+    use actix_web::test;
+    use std::collections::BTreeMap;
+
+    fn body_str(resp: &HttpResponse) -> String {
+        match resp.body() {
+            ResponseBody::Body(Body::Bytes(b)) => String::from_utf8(b.to_vec()).unwrap(),
+            ResponseBody::Body(Body::Empty) => String::new(),
+            _ => panic!("unexpected body"),
+        }
+    }
+
+    #[test]
+    fn test_status_and_headers() {
+        let req = test::TestRequest::default()
+            .header("X-Request-Id", "req-1")
+            .to_http_request();
+        let resp = ResponseWrapper::created(1)
+            .header("Location", "/users/1")
+            .trace_id("trace-1")
+            .into_response(Some(&req));
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers().get("Location").unwrap(), "/users/1");
+        assert_eq!(
+            body_str(&resp),
+            r#"{"code":0,"msg":"success","data":1,"request_id":"req-1","trace_id":"trace-1"}"#
+        );
+
+        let resp = ResponseWrapper::<()>::err(40400, "not found").into_response(None);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(ResponseWrapper::<()>::err(1, "x").get_status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let resp = ResponseWrapper::no_content().into_response(None);
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(body_str(&resp), "");
+    }
+
+    #[test]
+    fn test_serialize_error() {
+        // JSON 对象的 key 只能是字符串，序列化会失败
+        let mut data = BTreeMap::new();
+        data.insert((1, 2), "x");
+        let resp = ResponseWrapper::ok(data).into_response(None);
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body_str(&resp).contains("50000"));
+    }
+}
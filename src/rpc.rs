@@ -0,0 +1,156 @@
+//! WebSocket 上的 JSON 请求/响应（`/ws/rpc`）
+//!
+//! 客户端的文本帧解析为 `RpcRequest`，`method` 和 `params` 对应 `RpcCommand`，调用与 HTTP 接口相同的处理函数。
+//! 响应带有请求的 `id`，其余字段与 `ResponseWrapper` 相同，错误码见 `AppError::code`；无法解析出 `id` 时为 null。
+//! 同一连接上的请求并发处理，响应顺序不一定与请求顺序相同。
+//! 服务端主动推送的 `RpcEvent` 没有 `id`，通过 `event` 区分。
+//!
+//! ```text
+//! <- {"event": "ready", "data": {"user_id": null}}
+//! -> {"id": 1, "method": "users.get", "params": {"id": 1}}
+//! <- {"id": 1, "code": 0, "msg": "success", "data": {"id": 1, "name": "xiaoming", ...}}
+//! -> {"id": 2, "method": "users.list", "params": {"limit": 10, "cursor": 20}}
+//! <- {"id": 2, "code": 0, "msg": "success", "data": [...], "page": {"limit": 10, "next_cursor": 30}}
+//! -> {"id": 3, "method": "me"}
+//! <- {"id": 3, "code": 40100, "msg": "login required", "data": null}
+//! <- {"event": "shutdown", "data": null}
+//! ```
+
+use actix::Message;
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::handlers::list::{ListQuery, RawListQuery};
+use crate::handlers::users;
+use crate::{AppError, PoolConnection, ResponseWrapper};
+
+/// 支持的方法，`params` 为对应的参数
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", content = "params")]
+pub enum RpcCommand {
+    #[serde(rename = "ping")]
+    Ping,
+    /// 当前登录的用户
+    #[serde(rename = "me")]
+    Me,
+    #[serde(rename = "users.get")]
+    UsersGet { id: i64 },
+    /// 参数与 `GET /users` 的查询参数相同，没有参数时 `params` 为 `{}`
+    #[serde(rename = "users.list")]
+    UsersList(RawListQuery),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RpcRequest {
+    pub id: u64,
+    #[serde(flatten)]
+    pub command: RpcCommand,
+}
+
+#[derive(Serialize)]
+pub struct RpcResponse<T> {
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub body: ResponseWrapper<T>,
+}
+
+/// 服务端推送的事件
+#[derive(Debug, Clone, PartialEq, Serialize, Message)]
+#[rtype(result = "()")]
+pub struct RpcEvent {
+    pub event: String,
+    pub data: Value,
+}
+
+impl RpcEvent {
+    pub fn new(event: impl Into<String>, data: Value) -> Self {
+        RpcEvent { event: event.into(), data }
+    }
+}
+
+/// 处理请求需要的依赖，每个连接一份
+#[derive(Clone)]
+pub struct RpcContext {
+    pub pool: web::Data<PoolConnection>,
+    /// 建立连接时登录的用户
+    pub user_id: Option<i64>,
+}
+
+/// 解析文本帧，失败时返回序列化后的错误响应
+pub fn parse(text: &str) -> Result<RpcRequest, String> {
+    let value = serde_json::from_str::<Value>(text)
+        .map_err(|e| error(None, AppError::BadRequest(format!("invalid json: {}", e))))?;
+    let id = value.get("id").and_then(Value::as_u64);
+    serde_json::from_value(value).map_err(|e| error(id, AppError::BadRequest(format!("invalid request: {}", e))))
+}
+
+/// 执行请求，返回序列化后的响应
+pub async fn dispatch(ctx: RpcContext, req: RpcRequest) -> String {
+    let id = Some(req.id);
+    match req.command {
+        RpcCommand::Ping => reply(id, Ok(ResponseWrapper::ok("pong"))),
+        RpcCommand::Me => {
+            let user_id = match ctx.user_id {
+                Some(user_id) => user_id,
+                None => return error(id, AppError::Unauthorized("login required".to_string())),
+            };
+            // 与 CurrentUser 一致，用户已被删除时视为未登录
+            let result = users::users_get(ctx.pool, user_id.into()).await.map_err(|e| match e {
+                AppError::NotFound(_) => AppError::Unauthorized("login required".to_string()),
+                e => e,
+            });
+            reply(id, result)
+        }
+        RpcCommand::UsersGet { id: user_id } => reply(id, users::users_get(ctx.pool, user_id.into()).await),
+        RpcCommand::UsersList(raw) => match ListQuery::from_raw(raw) {
+            Ok(q) => reply(id, users::users_list(ctx.pool, q).await),
+            Err(e) => error(id, e),
+        },
+    }
+}
+
+/// 序列化响应
+pub fn reply<T: Serialize>(id: Option<u64>, result: Result<ResponseWrapper<T>, AppError>) -> String {
+    match result {
+        Ok(body) => serde_json::to_string(&RpcResponse { id, body }).unwrap_or_else(|e| error(id, AppError::internal(e))),
+        Err(e) => error(id, e),
+    }
+}
+
+/// 序列化错误响应，格式与 HTTP 接口的错误相同
+pub fn error(id: Option<u64>, e: AppError) -> String {
+    let body = ResponseWrapper::<()>::from(&e);
+    serde_json::to_string(&RpcResponse { id, body }).expect("error response is always serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_error(text: &str) -> Value {
+        serde_json::from_str(&parse(text).unwrap_err()).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let req = parse(r#"{"id": 1, "method": "users.get", "params": {"id": 2}}"#).unwrap();
+        assert_eq!(req.id, 1);
+        assert!(matches!(req.command, RpcCommand::UsersGet { id: 2 }));
+        let req = parse(r#"{"id": 2, "method": "users.list", "params": {"limit": 10}}"#).unwrap();
+        assert!(matches!(req.command, RpcCommand::UsersList(RawListQuery { limit: Some(10), .. })));
+        assert!(matches!(parse(r#"{"id": 3, "method": "ping"}"#).unwrap().command, RpcCommand::Ping));
+        assert!(matches!(parse(r#"{"id": 3, "method": "users.list", "params": {}}"#).unwrap().command, RpcCommand::UsersList(_)));
+
+        // 能解析出 id 时带上 id
+        let resp = parse_error(r#"{"id": 4, "method": "users.delete"}"#);
+        assert_eq!(resp["id"], 4);
+        assert_eq!(resp["code"], 40000);
+        assert!(resp["msg"].as_str().unwrap().starts_with("invalid request"));
+        assert_eq!(parse_error(r#"{"id": 5, "method": "users.list"}"#)["msg"], "invalid request: missing field `params`");
+        assert_eq!(parse_error(r#"{"method": "ping"}"#)["id"], Value::Null);
+        let resp = parse_error("hello");
+        assert_eq!(resp, json!({"id": null, "code": 40000, "msg": resp["msg"], "data": null}));
+    }
+}
@@ -1,3 +1,10 @@
+table! {
+    counters (name) {
+        name -> Varchar,
+        value -> Bigint,
+    }
+}
+
 table! {
     posts (id) {
         id -> Bigint,
@@ -8,17 +15,54 @@ table! {
     }
 }
 
+table! {
+    role_permissions (role, permission) {
+        role -> Varchar,
+        permission -> Varchar,
+    }
+}
+
+table! {
+    revoked_tokens (jti) {
+        jti -> Varchar,
+        user_id -> Bigint,
+        expires_at -> Timestamp,
+        revoked_at -> Timestamp,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Varchar,
+        data -> Text,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    user_roles (user_id, role) {
+        user_id -> Bigint,
+        role -> Varchar,
+    }
+}
+
 table! {
     users (id) {
         id -> Bigint,
-        name -> Text,
+        name -> Varchar,
         hair_color -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        password_hash -> Nullable<Varchar>,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
+    counters,
     posts,
+    revoked_tokens,
+    role_permissions,
+    sessions,
+    user_roles,
     users,
 );
@@ -0,0 +1,264 @@
+//! 服务端 session
+//!
+//! `middleware::ServerSession` 替代 actix-session 的 `CookieSession`：cookie 中只保存签名的随机 session id，
+//! 数据保存在 `SessionStore` 中（`sessions` 表或进程内存），handler 仍然使用 `actix_session::Session` 读写。
+//!
+//! - 登录时调用 `Session::renew` 更换 session id，数据保留，旧 id 立即失效
+//! - `Session::purge` 同时删除服务端数据和 cookie
+//! - session 在 `session.ttl` 秒内没有修改时过期，剩余有效期不足一半时自动延长
+//! - cookie 使用 `session.keys` 中的第一个密钥签名，其余密钥只用于验证，使用旧密钥签名的 cookie 会重新签名
+
+use actix_web::cookie::{Cookie, CookieJar, Key, SameSite};
+use actix_web::web;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use futures::future::{ready, LocalBoxFuture};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::handlers::db_conn;
+use crate::settings::{CookieSameSite, SessionSettings, SessionStoreKind};
+use crate::{model, schema, AppError, PoolConnection};
+
+/// session 数据，值为 JSON，与 `actix_session::Session` 一致
+pub type SessionState = HashMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSession {
+    pub state: SessionState,
+    pub expires_at: NaiveDateTime,
+}
+
+impl StoredSession {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+}
+
+/// session 的存储后端，所有 worker 共用
+pub trait SessionStore: Send + Sync {
+    /// 读取未过期的 session
+    fn load(&self, id: String) -> LocalBoxFuture<'static, Result<Option<StoredSession>, AppError>>;
+    /// 创建或覆盖
+    fn save(&self, id: String, session: StoredSession) -> LocalBoxFuture<'static, Result<(), AppError>>;
+    fn remove(&self, id: String) -> LocalBoxFuture<'static, Result<(), AppError>>;
+}
+
+/// 保存在进程内存中，写入时删除过期的 session
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, StoredSession>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// 未过期的 session 数
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().values().filter(|s| !s.is_expired()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: String) -> LocalBoxFuture<'static, Result<Option<StoredSession>, AppError>> {
+        let session = self.sessions.lock().unwrap().get(&id).filter(|s| !s.is_expired()).cloned();
+        Box::pin(ready(Ok(session)))
+    }
+
+    fn save(&self, id: String, session: StoredSession) -> LocalBoxFuture<'static, Result<(), AppError>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| !s.is_expired());
+        sessions.insert(id, session);
+        Box::pin(ready(Ok(())))
+    }
+
+    fn remove(&self, id: String) -> LocalBoxFuture<'static, Result<(), AppError>> {
+        self.sessions.lock().unwrap().remove(&id);
+        Box::pin(ready(Ok(())))
+    }
+}
+
+/// 保存在 `sessions` 表中，写入时删除过期的记录
+pub struct DbStore {
+    pool: PoolConnection,
+}
+
+impl DbStore {
+    pub fn new(pool: PoolConnection) -> Self {
+        DbStore { pool }
+    }
+}
+
+impl SessionStore for DbStore {
+    fn load(&self, id: String) -> LocalBoxFuture<'static, Result<Option<StoredSession>, AppError>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            use schema::sessions;
+            let conn = db_conn(&pool).await?;
+            let record = web::block(move || {
+                sessions::table
+                    .find(id)
+                    .filter(sessions::expires_at.gt(Utc::now().naive_utc()))
+                    .first::<model::SessionRecord>(&conn)
+                    .optional()
+            }).await?;
+            // 无法解析的数据当作不存在
+            Ok(record.and_then(|record| match serde_json::from_str(&record.data) {
+                Ok(state) => Some(StoredSession { state, expires_at: record.expires_at }),
+                Err(e) => {
+                    log::warn!("invalid session data: {}", e);
+                    None
+                }
+            }))
+        })
+    }
+
+    fn save(&self, id: String, session: StoredSession) -> LocalBoxFuture<'static, Result<(), AppError>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            use schema::sessions;
+            let record = model::SessionRecord {
+                id,
+                data: serde_json::to_string(&session.state).map_err(AppError::internal)?,
+                expires_at: session.expires_at,
+            };
+            let conn = db_conn(&pool).await?;
+            web::block(move || {
+                conn.transaction::<_, diesel::result::Error, _>(|| {
+                    diesel::delete(sessions::table.filter(sessions::expires_at.le(Utc::now().naive_utc())))
+                        .execute(&conn)?;
+                    diesel::delete(sessions::table.find(&record.id)).execute(&conn)?;
+                    diesel::insert_into(sessions::table).values(&record).execute(&conn)?;
+                    Ok(())
+                })
+            }).await?;
+            Ok(())
+        })
+    }
+
+    fn remove(&self, id: String) -> LocalBoxFuture<'static, Result<(), AppError>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            use schema::sessions;
+            let conn = db_conn(&pool).await?;
+            web::block(move || diesel::delete(sessions::table.find(id)).execute(&conn)).await?;
+            Ok(())
+        })
+    }
+}
+
+pub fn new_store(kind: SessionStoreKind, pool: PoolConnection) -> Arc<dyn SessionStore> {
+    match kind {
+        SessionStoreKind::Database => Arc::new(DbStore::new(pool)),
+        SessionStoreKind::Memory => Arc::new(MemoryStore::new()),
+    }
+}
+
+/// 随机生成的 session id，64 个十六进制字符
+pub fn new_session_id() -> String {
+    format!("{:032x}{:032x}", rand::random::<u128>(), rand::random::<u128>())
+}
+
+/// 从 cookie 中验证得到的 session id
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionCookie {
+    pub id: String,
+    /// 使用旧密钥签名，需要重新签名
+    pub outdated: bool,
+}
+
+struct Inner {
+    store: Arc<dyn SessionStore>,
+    keys: Vec<Key>,
+    settings: SessionSettings,
+}
+
+/// 存储后端、签名密钥和 cookie 配置，clone 后共享，所有 worker 需要使用同一份（随机生成的密钥）
+#[derive(Clone)]
+pub struct Sessions {
+    inner: Arc<Inner>,
+}
+
+impl Sessions {
+    pub fn new(settings: &SessionSettings, store: Arc<dyn SessionStore>) -> Self {
+        Sessions {
+            inner: Arc::new(Inner {
+                store,
+                keys: settings.key_bytes().iter().map(|key| Key::from_master(key)).collect(),
+                settings: settings.clone(),
+            }),
+        }
+    }
+
+    pub fn store(&self) -> &dyn SessionStore {
+        &*self.inner.store
+    }
+
+    pub fn cookie_name(&self) -> &str {
+        &self.inner.settings.cookie_name
+    }
+
+    /// 新的过期时间
+    pub fn expires_at(&self) -> NaiveDateTime {
+        Utc::now().naive_utc() + self.ttl()
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::seconds(self.inner.settings.ttl as i64)
+    }
+
+    /// 剩余有效期不足一半时需要延长
+    pub fn needs_refresh(&self, session: &StoredSession) -> bool {
+        session.expires_at - Utc::now().naive_utc() < self.ttl() / 2
+    }
+
+    /// 依次使用每个密钥验证签名
+    pub fn verify(&self, cookie: &Cookie<'static>) -> Option<SessionCookie> {
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie.clone());
+        self.inner.keys.iter().enumerate().find_map(|(i, key)| {
+            jar.signed(key).get(cookie.name()).map(|cookie| SessionCookie {
+                id: cookie.value().to_string(),
+                outdated: i > 0,
+            })
+        })
+    }
+
+    /// 使用第一个密钥签名的 cookie
+    pub fn cookie(&self, id: &str) -> Cookie<'static> {
+        let cookie = self.build_cookie(id.to_string(), self.inner.settings.ttl as i64);
+        let mut jar = CookieJar::new();
+        jar.signed(&self.inner.keys[0]).add(cookie);
+        jar.get(self.cookie_name()).cloned().expect("cookie was just added")
+    }
+
+    /// 让浏览器删除 cookie，Path 和 Domain 需要与设置时一致
+    pub fn removal_cookie(&self) -> Cookie<'static> {
+        self.build_cookie(String::new(), 0)
+    }
+
+    fn build_cookie(&self, value: String, max_age: i64) -> Cookie<'static> {
+        let settings = &self.inner.settings;
+        let mut cookie = Cookie::build(settings.cookie_name.clone(), value)
+            .path(settings.cookie_path.clone())
+            .secure(settings.secure)
+            .http_only(settings.http_only)
+            .max_age(max_age)
+            .finish();
+        if let Some(domain) = &settings.cookie_domain {
+            cookie.set_domain(domain.clone());
+        }
+        match settings.same_site {
+            CookieSameSite::Strict => cookie.set_same_site(SameSite::Strict),
+            CookieSameSite::Lax => cookie.set_same_site(SameSite::Lax),
+            CookieSameSite::None => {}
+        }
+        cookie
+    }
+}
@@ -0,0 +1,821 @@
+//! 应用配置
+//!
+//! 优先级：默认值 < 配置文件 < 环境变量（包括 `.env`）< 命令行参数
+//!
+//! | 配置项                       | 环境变量                      | 命令行参数  |
+//! |------------------------------|-------------------------------|-------------|
+//! | `server.host`                | `SERVER_HOST`                 | `--host`    |
+//! | `server.port`                | `SERVER_PORT`                 | `--port`    |
+//! | `server.workers`             | `SERVER_WORKERS`              | `--workers` |
+//! | `server.json_limit`          | `SERVER_JSON_LIMIT`           |             |
+//! | `server.shutdown_timeout`    | `SERVER_SHUTDOWN_TIMEOUT`     |             |
+//! | `server.readiness_timeout`   | `SERVER_READINESS_TIMEOUT`    |             |
+//! | `app.name`                   | `APP_NAME`                    |             |
+//! | `app.version`                | `APP_VERSION`                 |             |
+//! | `session.store`              | `SESSION_STORE`               |             |
+//! | `session.keys`               | `SESSION_KEYS`（逗号分隔）    |             |
+//! | `session.ttl`                | `SESSION_TTL`                 |             |
+//! | `session.cookie_domain`      | `SESSION_COOKIE_DOMAIN`       |             |
+//! | `session.secure`             | `SESSION_SECURE`              |             |
+//! | `session.same_site`          | `SESSION_SAME_SITE`           |             |
+//! | `jwt.algorithm`              | `JWT_ALGORITHM`               |             |
+//! | `jwt.secret`                 | `JWT_SECRET`                  |             |
+//! | `jwt.private_key`            | `JWT_PRIVATE_KEY`             |             |
+//! | `jwt.public_key`             | `JWT_PUBLIC_KEY`              |             |
+//! | `jwt.access_ttl`             | `JWT_ACCESS_TTL`              |             |
+//! | `jwt.refresh_ttl`            | `JWT_REFRESH_TTL`             |             |
+//! | `log.filter`                 | `RUST_LOG`                    | `--log`     |
+//! | `log.format`                 | `LOG_FORMAT`                  |             |
+//! | `trace.enabled`              | `TRACE_ENABLED`               |             |
+//! | `trace.include`              | `TRACE_INCLUDE`（逗号分隔）   |             |
+//! | `trace.exclude`              | `TRACE_EXCLUDE`（逗号分隔）   |             |
+//! | `trace.output`               | `TRACE_OUTPUT`                |             |
+//! | `counters.persist`           | `COUNTERS_PERSIST`            |             |
+//! | `counters.persist_interval`  | `COUNTERS_PERSIST_INTERVAL`   |             |
+//! | `counters.max_counters`      | `COUNTERS_MAX`                |             |
+//! | `ws.heartbeat_interval`      | `WS_HEARTBEAT_INTERVAL`       |             |
+//! | `ws.client_timeout`          | `WS_CLIENT_TIMEOUT`           |             |
+//! | `ws.max_message_size`        | `WS_MAX_MESSAGE_SIZE`         |             |
+//! | `events.history_size`        | `EVENTS_HISTORY_SIZE`         |             |
+//! | `events.buffer_size`         | `EVENTS_BUFFER_SIZE`          |             |
+//! | `events.keep_alive`          | `EVENTS_KEEP_ALIVE`           |             |
+//! | `database.*`                 | `DATABASE_*`                  |             |
+
+use dotenv::dotenv;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{env, fs};
+use structopt::StructOpt;
+
+use crate::{DbConfig, DbError};
+
+/// 签名 cookie 的密钥最少字节数
+pub const SESSION_KEY_MIN_LEN: usize = 32;
+
+/// HS256 密钥最少字节数
+pub const JWT_SECRET_MIN_LEN: usize = 32;
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub app: AppSettings,
+    pub session: SessionSettings,
+    pub jwt: JwtSettings,
+    pub log: LogSettings,
+    pub trace: TraceSettings,
+    pub counters: CounterSettings,
+    pub ws: WsSettings,
+    pub events: EventsSettings,
+    pub database: DbConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    /// 工作线程数，默认为 CPU 核数
+    pub workers: Option<usize>,
+    /// JSON 请求体大小上限（字节）
+    pub json_limit: usize,
+    /// 退出时等待进行中的请求完成的最长时间（秒）
+    pub shutdown_timeout: u64,
+    /// `/readyz` 检查数据库的超时时间（秒）
+    pub readiness_timeout: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            host: "127.0.0.1".to_string(),
+            port: 8088,
+            workers: None,
+            json_limit: 4096,
+            shutdown_timeout: 30,
+            readiness_timeout: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppSettings {
+    pub name: String,
+    /// 通过 X-Version 响应头返回
+    pub version: String,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            name: "Actix-web".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// 服务端 session，见 `crate::session`
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSettings {
+    /// session 数据的保存位置
+    pub store: SessionStoreKind,
+    /// cookie 签名密钥，每个至少 32 字节；第一个用于签名，其余只用于验证，轮换时把旧密钥放在后面。
+    /// 为空时启动时随机生成，重启后旧 session 失效
+    pub keys: Vec<String>,
+    /// 有效期（秒），从最后一次修改算起，剩余不足一半时自动延长
+    pub ttl: u64,
+    pub cookie_name: String,
+    pub cookie_path: String,
+    pub cookie_domain: Option<String>,
+    /// 只通过 https 发送 cookie
+    pub secure: bool,
+    /// 禁止 JavaScript 读取 cookie
+    pub http_only: bool,
+    pub same_site: CookieSameSite,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        SessionSettings {
+            store: SessionStoreKind::Database,
+            keys: Vec::new(),
+            ttl: 7 * 24 * 60 * 60,
+            cookie_name: "actix-session".to_string(),
+            cookie_path: "/".to_string(),
+            cookie_domain: None,
+            secure: false,
+            http_only: true,
+            same_site: CookieSameSite::Lax,
+        }
+    }
+}
+
+// 不打印密钥
+impl fmt::Debug for SessionSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SessionSettings")
+            .field("store", &self.store)
+            .field("keys", &self.keys.iter().map(|_| "***").collect::<Vec<_>>())
+            .field("ttl", &self.ttl)
+            .field("cookie_name", &self.cookie_name)
+            .field("cookie_path", &self.cookie_path)
+            .field("cookie_domain", &self.cookie_domain)
+            .field("secure", &self.secure)
+            .field("http_only", &self.http_only)
+            .field("same_site", &self.same_site)
+            .finish()
+    }
+}
+
+impl SessionSettings {
+    /// 签名和验证使用的密钥，第一个用于签名
+    pub fn key_bytes(&self) -> Vec<Vec<u8>> {
+        if self.keys.is_empty() {
+            log::warn!("session.keys is not set, using a random key");
+            return vec![(0..SESSION_KEY_MIN_LEN).map(|_| rand::random::<u8>()).collect()];
+        }
+        self.keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// `sessions` 表，多个进程共享
+    Database,
+    /// 进程内存，重启后丢失，用于测试和单进程部署
+    Memory,
+}
+
+impl FromStr for SessionStoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "database" => Ok(SessionStoreKind::Database),
+            "memory" => Ok(SessionStoreKind::Memory),
+            _ => Err(format!("unknown session store: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+    /// 不设置 SameSite 属性
+    None,
+}
+
+impl FromStr for CookieSameSite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(CookieSameSite::Strict),
+            "lax" => Ok(CookieSameSite::Lax),
+            "none" => Ok(CookieSameSite::None),
+            _ => Err(format!("unknown same_site policy: {}", s)),
+        }
+    }
+}
+
+/// Bearer token（JWT），见 `crate::token`
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtSettings {
+    pub algorithm: JwtAlgorithm,
+    /// HS256 的密钥，至少 32 字节；为空时启动时随机生成，重启后旧 token 失效
+    pub secret: Option<String>,
+    /// RS256 签发 token 的私钥（PEM 文件路径）
+    pub private_key: Option<PathBuf>,
+    /// RS256 验证 token 的公钥（PEM 文件路径）
+    pub public_key: Option<PathBuf>,
+    /// 写入 `iss`，验证时也会检查
+    pub issuer: String,
+    /// access token 有效期（秒）
+    pub access_ttl: u64,
+    /// refresh token 有效期（秒）
+    pub refresh_ttl: u64,
+    /// 验证过期时间时允许的时钟误差（秒）
+    pub leeway: u64,
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        JwtSettings {
+            algorithm: JwtAlgorithm::HS256,
+            secret: None,
+            private_key: None,
+            public_key: None,
+            issuer: "actix-learn".to_string(),
+            access_ttl: 15 * 60,
+            refresh_ttl: 14 * 24 * 60 * 60,
+            leeway: 30,
+        }
+    }
+}
+
+// 不打印密钥
+impl fmt::Debug for JwtSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JwtSettings")
+            .field("algorithm", &self.algorithm)
+            .field("secret", &self.secret.as_ref().map(|_| "***"))
+            .field("private_key", &self.private_key)
+            .field("public_key", &self.public_key)
+            .field("issuer", &self.issuer)
+            .field("access_ttl", &self.access_ttl)
+            .field("refresh_ttl", &self.refresh_ttl)
+            .field("leeway", &self.leeway)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum JwtAlgorithm {
+    /// 共享密钥
+    HS256,
+    /// RSA 私钥签发，公钥验证
+    RS256,
+}
+
+impl FromStr for JwtAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "HS256" => Ok(JwtAlgorithm::HS256),
+            "RS256" => Ok(JwtAlgorithm::RS256),
+            _ => Err(format!("unsupported jwt algorithm: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    /// env_logger 的过滤规则，例如 `actix_web=info,actix_learn=debug`
+    /// 访问日志的 target 为 `actix_learn::access`
+    pub filter: String,
+    /// 日志格式，见 `crate::logging`
+    pub format: LogFormat,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            filter: "actix_web=info,actix_learn=info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 单行文本
+    Text,
+    /// 每行一个 JSON 对象
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format: {}", s)),
+        }
+    }
+}
+
+/// 请求追踪（调试用），见 `crate::trace`
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceSettings {
+    /// 启动时是否开启，运行时可以通过 `PUT /debug/trace` 切换
+    pub enabled: bool,
+    /// 只追踪匹配的路径，支持 `*` 通配符，为空时追踪所有路径
+    pub include: Vec<String>,
+    /// 不追踪的路径，优先于 include
+    pub exclude: Vec<String>,
+    /// 记录请求和响应头
+    pub capture_headers: bool,
+    /// 记录请求和响应体
+    pub capture_body: bool,
+    /// 每个请求体、响应体最多记录的字节数
+    pub max_body_size: usize,
+    /// 值替换为 `***` 的请求头和响应头，不区分大小写
+    pub redact_headers: Vec<String>,
+    /// 值替换为 `***` 的 JSON、表单字段和查询参数，不区分大小写
+    pub redact_fields: Vec<String>,
+    pub output: TraceOutput,
+    /// 内存中保留的最近请求数
+    pub buffer_size: usize,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        TraceSettings {
+            enabled: false,
+            include: Vec::new(),
+            exclude: strings(&["/metrics", "/healthz", "/readyz", "/debug/*"]),
+            capture_headers: true,
+            capture_body: true,
+            max_body_size: 4096,
+            redact_headers: strings(&["authorization", "cookie", "set-cookie", "x-api-key"]),
+            redact_fields: strings(&["password", "token", "access_token", "refresh_token", "secret"]),
+            output: TraceOutput::Buffer,
+            buffer_size: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceOutput {
+    /// 输出到日志，target 为 `actix_learn::trace`
+    Log,
+    /// 保存在内存中，通过 `GET /debug/requests` 查看
+    Buffer,
+    Both,
+}
+
+impl FromStr for TraceOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "log" => Ok(TraceOutput::Log),
+            "buffer" => Ok(TraceOutput::Buffer),
+            "both" => Ok(TraceOutput::Both),
+            _ => Err(format!("unknown trace output: {}", s)),
+        }
+    }
+}
+
+/// 命名计数器，见 `crate::counter`
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CounterSettings {
+    /// 是否持久化到数据库，关闭时重启后归零
+    pub persist: bool,
+    /// 持久化间隔（秒）
+    pub persist_interval: u64,
+    /// 最多创建的计数器数，计数器名同时是 `/metrics` 的 label 值，不能无限增长
+    pub max_counters: usize,
+}
+
+impl Default for CounterSettings {
+    fn default() -> Self {
+        CounterSettings { persist: false, persist_interval: 10, max_counters: 100 }
+    }
+}
+
+/// WebSocket 会话，见 `handlers::ws`
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WsSettings {
+    /// 发送 Ping 的间隔（秒）
+    pub heartbeat_interval: u64,
+    /// 超过这个时间（秒）没有收到客户端的任何帧时关闭连接
+    pub client_timeout: u64,
+    /// 单个消息（包括分片重组后）的最大字节数
+    pub max_message_size: usize,
+}
+
+impl Default for WsSettings {
+    fn default() -> Self {
+        WsSettings {
+            heartbeat_interval: 5,
+            client_timeout: 10,
+            max_message_size: 64 * 1024,
+        }
+    }
+}
+
+/// 文章变更通知，见 `crate::events`
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsSettings {
+    /// 保留最近的事件数，断线重连时通过 Last-Event-ID 补发
+    pub history_size: usize,
+    /// 每个订阅者最多缓存的事件数，超过时断开该订阅者
+    pub buffer_size: usize,
+    /// SSE 发送 keep-alive 注释的间隔（秒）
+    pub keep_alive: u64,
+}
+
+impl Default for EventsSettings {
+    fn default() -> Self {
+        EventsSettings {
+            history_size: 1000,
+            buffer_size: 256,
+            keep_alive: 15,
+        }
+    }
+}
+
+/// 可以覆盖配置的命令行参数
+#[derive(StructOpt, Debug, Default)]
+pub struct SettingsArgs {
+    /// 配置文件路径，文件不存在时忽略
+    #[structopt(short, long, default_value = "config.toml", parse(from_os_str))]
+    pub config: PathBuf,
+    /// 监听地址
+    #[structopt(long)]
+    pub host: Option<String>,
+    /// 监听端口
+    #[structopt(short, long)]
+    pub port: Option<u16>,
+    /// 工作线程数
+    #[structopt(long)]
+    pub workers: Option<usize>,
+    /// 日志过滤规则
+    #[structopt(long)]
+    pub log: Option<String>,
+}
+
+impl Settings {
+    /// 按优先级加载并校验配置
+    pub fn load(args: &SettingsArgs) -> Result<Self, SettingsError> {
+        let mut settings = Self::from_file(&args.config)?;
+        settings.apply_env()?;
+        settings.apply_args(args);
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// 读取配置文件，文件不存在时返回默认值
+    pub fn from_file(path: &Path) -> Result<Self, SettingsError> {
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| SettingsError::File(format!("{}: {}", path.display(), e)))?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, SettingsError> {
+        toml::from_str(content).map_err(|e| SettingsError::File(e.to_string()))
+    }
+
+    /// 使用环境变量覆盖
+    pub fn apply_env(&mut self) -> Result<(), SettingsError> {
+        dotenv().ok();
+
+        if let Ok(v) = env::var("SERVER_HOST") {
+            self.server.host = v;
+        }
+        if let Some(v) = env_parse("SERVER_PORT").map_err(SettingsError::Invalid)? {
+            self.server.port = v;
+        }
+        if let Some(v) = env_parse("SERVER_WORKERS").map_err(SettingsError::Invalid)? {
+            self.server.workers = Some(v);
+        }
+        if let Some(v) = env_parse("SERVER_JSON_LIMIT").map_err(SettingsError::Invalid)? {
+            self.server.json_limit = v;
+        }
+        if let Some(v) = env_parse("SERVER_SHUTDOWN_TIMEOUT").map_err(SettingsError::Invalid)? {
+            self.server.shutdown_timeout = v;
+        }
+        if let Some(v) = env_parse("SERVER_READINESS_TIMEOUT").map_err(SettingsError::Invalid)? {
+            self.server.readiness_timeout = v;
+        }
+        if let Ok(v) = env::var("APP_NAME") {
+            self.app.name = v;
+        }
+        if let Ok(v) = env::var("APP_VERSION") {
+            self.app.version = v;
+        }
+        if let Some(v) = env_parse("SESSION_STORE").map_err(SettingsError::Invalid)? {
+            self.session.store = v;
+        }
+        if let Some(v) = env_list("SESSION_KEYS") {
+            self.session.keys = v;
+        }
+        if let Some(v) = env_parse("SESSION_TTL").map_err(SettingsError::Invalid)? {
+            self.session.ttl = v;
+        }
+        if let Ok(v) = env::var("SESSION_COOKIE_DOMAIN") {
+            self.session.cookie_domain = Some(v);
+        }
+        if let Some(v) = env_parse_bool("SESSION_SECURE").map_err(SettingsError::Invalid)? {
+            self.session.secure = v;
+        }
+        if let Some(v) = env_parse("SESSION_SAME_SITE").map_err(SettingsError::Invalid)? {
+            self.session.same_site = v;
+        }
+        if let Some(v) = env_parse("JWT_ALGORITHM").map_err(SettingsError::Invalid)? {
+            self.jwt.algorithm = v;
+        }
+        if let Ok(v) = env::var("JWT_SECRET") {
+            self.jwt.secret = Some(v);
+        }
+        if let Ok(v) = env::var("JWT_PRIVATE_KEY") {
+            self.jwt.private_key = Some(v.into());
+        }
+        if let Ok(v) = env::var("JWT_PUBLIC_KEY") {
+            self.jwt.public_key = Some(v.into());
+        }
+        if let Some(v) = env_parse("JWT_ACCESS_TTL").map_err(SettingsError::Invalid)? {
+            self.jwt.access_ttl = v;
+        }
+        if let Some(v) = env_parse("JWT_REFRESH_TTL").map_err(SettingsError::Invalid)? {
+            self.jwt.refresh_ttl = v;
+        }
+        if let Ok(v) = env::var("RUST_LOG") {
+            self.log.filter = v;
+        }
+        if let Some(v) = env_parse("LOG_FORMAT").map_err(SettingsError::Invalid)? {
+            self.log.format = v;
+        }
+        if let Some(v) = env_parse_bool("TRACE_ENABLED").map_err(SettingsError::Invalid)? {
+            self.trace.enabled = v;
+        }
+        if let Some(v) = env_list("TRACE_INCLUDE") {
+            self.trace.include = v;
+        }
+        if let Some(v) = env_list("TRACE_EXCLUDE") {
+            self.trace.exclude = v;
+        }
+        if let Some(v) = env_parse("TRACE_OUTPUT").map_err(SettingsError::Invalid)? {
+            self.trace.output = v;
+        }
+        if let Some(v) = env_parse_bool("COUNTERS_PERSIST").map_err(SettingsError::Invalid)? {
+            self.counters.persist = v;
+        }
+        if let Some(v) = env_parse("COUNTERS_PERSIST_INTERVAL").map_err(SettingsError::Invalid)? {
+            self.counters.persist_interval = v;
+        }
+        if let Some(v) = env_parse("COUNTERS_MAX").map_err(SettingsError::Invalid)? {
+            self.counters.max_counters = v;
+        }
+        if let Some(v) = env_parse("WS_HEARTBEAT_INTERVAL").map_err(SettingsError::Invalid)? {
+            self.ws.heartbeat_interval = v;
+        }
+        if let Some(v) = env_parse("WS_CLIENT_TIMEOUT").map_err(SettingsError::Invalid)? {
+            self.ws.client_timeout = v;
+        }
+        if let Some(v) = env_parse("WS_MAX_MESSAGE_SIZE").map_err(SettingsError::Invalid)? {
+            self.ws.max_message_size = v;
+        }
+        if let Some(v) = env_parse("EVENTS_HISTORY_SIZE").map_err(SettingsError::Invalid)? {
+            self.events.history_size = v;
+        }
+        if let Some(v) = env_parse("EVENTS_BUFFER_SIZE").map_err(SettingsError::Invalid)? {
+            self.events.buffer_size = v;
+        }
+        if let Some(v) = env_parse("EVENTS_KEEP_ALIVE").map_err(SettingsError::Invalid)? {
+            self.events.keep_alive = v;
+        }
+        self.database.apply_env()?;
+        Ok(())
+    }
+
+    /// 使用命令行参数覆盖
+    pub fn apply_args(&mut self, args: &SettingsArgs) {
+        if let Some(host) = &args.host {
+            self.server.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            self.server.port = port;
+        }
+        if let Some(workers) = args.workers {
+            self.server.workers = Some(workers);
+        }
+        if let Some(log) = &args.log {
+            self.log.filter = log.clone();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.server.host.is_empty() {
+            return Err(SettingsError::Invalid("server.host must not be empty".to_string()));
+        }
+        if self.server.workers == Some(0) {
+            return Err(SettingsError::Invalid("server.workers must be greater than 0".to_string()));
+        }
+        if self.server.readiness_timeout == 0 {
+            return Err(SettingsError::Invalid("server.readiness_timeout must be greater than 0".to_string()));
+        }
+        if self.server.json_limit == 0 {
+            return Err(SettingsError::Invalid("server.json_limit must be greater than 0".to_string()));
+        }
+        if self.session.keys.iter().any(|key| key.len() < SESSION_KEY_MIN_LEN) {
+            return Err(SettingsError::Invalid(format!(
+                "session.keys must be at least {} bytes each",
+                SESSION_KEY_MIN_LEN
+            )));
+        }
+        if self.session.ttl == 0 {
+            return Err(SettingsError::Invalid("session.ttl must be greater than 0".to_string()));
+        }
+        if self.session.cookie_name.is_empty() {
+            return Err(SettingsError::Invalid("session.cookie_name must not be empty".to_string()));
+        }
+        if matches!(&self.jwt.secret, Some(secret) if secret.len() < JWT_SECRET_MIN_LEN) {
+            return Err(SettingsError::Invalid(format!(
+                "jwt.secret must be at least {} bytes",
+                JWT_SECRET_MIN_LEN
+            )));
+        }
+        if self.jwt.access_ttl == 0 || self.jwt.refresh_ttl == 0 {
+            return Err(SettingsError::Invalid("jwt.access_ttl and jwt.refresh_ttl must be greater than 0".to_string()));
+        }
+        // 启动前检查密钥文件能否读取和解析
+        crate::token::check_keys(&self.jwt)?;
+        if self.trace.output != TraceOutput::Log && self.trace.buffer_size == 0 {
+            return Err(SettingsError::Invalid("trace.buffer_size must be greater than 0".to_string()));
+        }
+        if self.counters.persist && self.counters.persist_interval == 0 {
+            return Err(SettingsError::Invalid("counters.persist_interval must be greater than 0".to_string()));
+        }
+        if self.ws.heartbeat_interval == 0 || self.ws.client_timeout <= self.ws.heartbeat_interval {
+            return Err(SettingsError::Invalid(
+                "ws.heartbeat_interval must be greater than 0 and less than ws.client_timeout".to_string(),
+            ));
+        }
+        if self.ws.max_message_size == 0 {
+            return Err(SettingsError::Invalid("ws.max_message_size must be greater than 0".to_string()));
+        }
+        if self.events.buffer_size == 0 || self.events.keep_alive == 0 {
+            return Err(SettingsError::Invalid(
+                "events.buffer_size and events.keep_alive must be greater than 0".to_string(),
+            ));
+        }
+        self.database.validate()?;
+        Ok(())
+    }
+
+    /// 监听地址，例如 `127.0.0.1:8088`
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.server.host, self.server.port)
+    }
+}
+
+pub(crate) fn env_parse<T: FromStr>(key: &str) -> Result<Option<T>, String> {
+    match env::var(key) {
+        Ok(v) => v.parse()
+            .map(Some)
+            .map_err(|_| format!("invalid value for {}: {}", key, v)),
+        Err(_) => Ok(None),
+    }
+}
+
+// 逗号分隔的列表，忽略空项
+pub(crate) fn env_list(key: &str) -> Option<Vec<String>> {
+    env::var(key).ok().map(|v| {
+        v.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+pub(crate) fn env_parse_bool(key: &str) -> Result<Option<bool>, String> {
+    match env::var(key) {
+        Ok(v) => match v.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Some(true)),
+            "0" | "false" | "no" | "off" => Ok(Some(false)),
+            _ => Err(format!("invalid value for {}: {}", key, v)),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    File(String),
+    Invalid(String),
+    Database(DbError),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::File(msg) => write!(f, "failed to load config file: {}", msg),
+            SettingsError::Invalid(msg) => write!(f, "invalid config: {}", msg),
+            SettingsError::Database(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<DbError> for SettingsError {
+    fn from(e: DbError) -> Self {
+        SettingsError::Database(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let settings = Settings::from_toml(r#"
+            [server]
+            port = 9000
+
+            [app]
+            name = "test"
+
+            [session]
+            store = "memory"
+            same_site = "strict"
+
+            [log]
+            format = "json"
+
+            [database]
+            url = "test.db"
+        "#).unwrap();
+        assert_eq!(settings.server.port, 9000);
+        assert_eq!(settings.server.host, "127.0.0.1");
+        assert_eq!(settings.app.name, "test");
+        assert_eq!(settings.session.store, SessionStoreKind::Memory);
+        assert_eq!(settings.session.same_site, CookieSameSite::Strict);
+        assert_eq!(settings.log.format, LogFormat::Json);
+        assert_eq!(settings.database.url, "test.db");
+        assert!(settings.validate().is_ok());
+
+        assert!(Settings::from_toml("[server]\naddress = \"0.0.0.0\"").is_err());
+    }
+
+    #[test]
+    fn test_args_and_validate() {
+        let mut settings = Settings::from_toml("[database]\nurl = \"test.db\"").unwrap();
+        settings.apply_args(&SettingsArgs {
+            host: Some("0.0.0.0".to_string()),
+            port: Some(80),
+            ..SettingsArgs::default()
+        });
+        assert_eq!(settings.bind_address(), "0.0.0.0:80");
+        assert!(settings.validate().is_ok());
+
+        settings.session.keys = vec!["0123456789abcdef0123456789abcdef".to_string(), "too short".to_string()];
+        assert!(matches!(settings.validate(), Err(SettingsError::Invalid(_))));
+
+        settings.session.keys.clear();
+        settings.jwt.algorithm = JwtAlgorithm::RS256;
+        assert!(matches!(settings.validate(), Err(SettingsError::Invalid(_))));
+        settings.jwt.private_key = Some("tests/keys/rs256_private.pem".into());
+        settings.jwt.public_key = Some("tests/keys/rs256_public.pem".into());
+        assert!(settings.validate().is_ok());
+
+        settings.database.url.clear();
+        assert!(matches!(settings.validate(), Err(SettingsError::Database(DbError::MissingUrl))));
+    }
+}
@@ -0,0 +1,218 @@
+//! 优雅退出
+//!
+//! 收到 SIGINT 或 SIGTERM 后：
+//! 1. 标记为正在退出（之后 `/readyz` 返回失败）
+//! 2. 向所有 WebSocket 会话发送 Close 帧并停止会话
+//! 3. 停止接受新连接，等待进行中的请求完成，最多等待 `server.shutdown_timeout` 秒
+//! 4. 等待连接池中的连接全部归还（`web::block` 中的数据库操作执行完）
+//!
+//! r2d2 没有关闭连接池的接口，最后一个 `PoolConnection` 被 drop 时关闭所有连接。
+
+use actix::{Message, Recipient};
+use actix_web::dev::Server;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::PoolConnection;
+
+/// 通知 WebSocket 会话服务即将退出
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ServerShutdown;
+
+#[derive(Default)]
+struct Inner {
+    shutting_down: AtomicBool,
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, Recipient<ServerShutdown>>>,
+}
+
+/// 退出状态和需要通知的 WebSocket 会话，clone 后共享同一份数据
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown::default()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.inner.shutting_down.load(Ordering::Acquire)
+    }
+
+    /// 注册 WebSocket 会话，会话结束时需要调用 `unregister`
+    pub fn register(&self, session: Recipient<ServerShutdown>) -> u64 {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        // 已经开始退出时直接通知
+        if self.is_shutting_down() {
+            let _ = session.do_send(ServerShutdown);
+        }
+        self.inner.sessions.lock().unwrap().insert(id, session);
+        id
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.inner.sessions.lock().unwrap().remove(&id);
+    }
+
+    /// 标记为正在退出并通知所有 WebSocket 会话，返回通知的会话数
+    pub fn begin(&self) -> usize {
+        self.inner.shutting_down.store(true, Ordering::Release);
+        let sessions = self.inner.sessions.lock().unwrap();
+        for session in sessions.values() {
+            let _ = session.do_send(ServerShutdown);
+        }
+        sessions.len()
+    }
+
+    /// 通知 WebSocket 会话，然后停止服务，等待进行中的请求完成
+    ///
+    /// 等待时间由 `HttpServer::shutdown_timeout` 决定
+    pub async fn stop(&self, server: &Server) {
+        let sessions = self.begin();
+        log::info!("shutting down, closing {} websocket sessions", sessions);
+        server.stop(true).await;
+    }
+
+    /// 等待监听 SIGINT 或 SIGTERM，然后调用 `stop`
+    ///
+    /// 需要在 `HttpServer::disable_signals` 之后使用，否则 actix 收到 SIGINT 时会直接退出
+    pub async fn stop_on_signal(self, server: Server) {
+        let signal = wait_for_signal().await;
+        log::info!("{} received", signal);
+        self.stop(&server).await;
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use actix_rt::signal::unix::{signal, SignalKind};
+    use futures::future::{select, Either};
+
+    let mut term = match signal(SignalKind::terminate()) {
+        Ok(term) => term,
+        Err(e) => {
+            log::warn!("failed to listen for SIGTERM: {}", e);
+            let _ = actix_rt::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    let int = Box::pin(actix_rt::signal::ctrl_c());
+    let signal = match select(int, Box::pin(term.recv())).await {
+        Either::Left(_) => "SIGINT",
+        Either::Right(_) => "SIGTERM",
+    };
+    signal
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    let _ = actix_rt::signal::ctrl_c().await;
+    "Ctrl-C"
+}
+
+/// 等待所有连接归还连接池，超时返回 false
+pub async fn wait_pool_idle(pool: &PoolConnection, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let state = pool.state();
+        if state.idle_connections >= state.connections {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            log::warn!(
+                "{} database connections still in use after {:?}",
+                state.connections - state.idle_connections,
+                timeout
+            );
+            return false;
+        }
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::ws;
+    use crate::Metrics;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use futures::{SinkExt, StreamExt};
+
+    #[actix_rt::test]
+    async fn test_graceful_shutdown() {
+        let shutdown = Shutdown::new();
+        let metrics = Metrics::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (s, m) = (shutdown.clone(), metrics.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .data(s.clone())
+                .data(m.clone())
+                .data(crate::settings::WsSettings::default())
+                .route("/slow", web::get().to(|| async {
+                    actix_rt::time::delay_for(Duration::from_millis(500)).await;
+                    Ok::<_, actix_web::Error>(HttpResponse::Ok().body("done"))
+                }))
+                .route("/ws", web::get().to(ws::ws_echo))
+        })
+        .workers(1)
+        .disable_signals()
+        .shutdown_timeout(5)
+        .listen(listener)
+        .unwrap()
+        .run();
+
+        let (_, mut framed) = awc::Client::new()
+            .ws(format!("http://{}/ws", addr))
+            .connect()
+            .await
+            .unwrap();
+        framed.send(awc::ws::Message::Text("hi".to_string())).await.unwrap();
+        assert!(matches!(framed.next().await, Some(Ok(awc::ws::Frame::Text(_)))));
+        assert_eq!(metrics.ws_connections(), 1);
+
+        // 空闲的 keep-alive 连接也会让服务等待，这里请求完成后直接关闭连接
+        let slow = async {
+            let mut resp = awc::Client::new().get(format!("http://{}/slow", addr)).force_close().send().await.unwrap();
+            (resp.status(), resp.body().await.unwrap())
+        };
+        let stop = async {
+            // 等待请求开始处理
+            actix_rt::time::delay_for(Duration::from_millis(100)).await;
+            shutdown.stop(&server).await;
+        };
+        let ((status, body), ()) = futures::join!(slow, stop);
+
+        // 进行中的请求正常完成
+        assert_eq!(status, 200);
+        assert_eq!(body, "done");
+        assert!(shutdown.is_shutting_down());
+
+        // WebSocket 收到 Close 帧
+        match framed.next().await {
+            Some(Ok(awc::ws::Frame::Close(Some(reason)))) => assert_eq!(reason.code, awc::ws::CloseCode::Away),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+        assert_eq!(metrics.ws_connections(), 0);
+
+        // 不再接受新连接
+        assert!(awc::Client::new().get(format!("http://{}/slow", addr)).send().await.is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[actix_rt::test]
+    async fn test_wait_pool_idle() {
+        let pool = crate::app::testing::test_pool();
+        let conn = pool.get().unwrap();
+        assert!(!wait_pool_idle(&pool, Duration::from_millis(100)).await);
+        drop(conn);
+        assert!(wait_pool_idle(&pool, Duration::from_millis(100)).await);
+    }
+}
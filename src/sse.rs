@@ -0,0 +1,312 @@
+//! Server-Sent Events
+//!
+//! `Sse` 把任意 `Stream<Item = Event>` 作为 `text/event-stream` 响应返回：
+//!
+//! - `Event` 按规范格式化 `id:` / `event:` / `data:` / `retry:` 字段，多行数据拆成多个 `data:` 行
+//! - 超过 `keep_alive` 没有发送任何内容时发送一行注释，避免代理因为空闲断开连接，同时尽早发现客户端断开
+//! - stream 结束时响应结束；客户端断开后响应体被 drop，stream 随之 drop，生产者停止
+//! - 在其他任务中产生事件时使用 `sse::channel`，`SseSender::send` 在客户端断开后返回错误
+//!
+//! ```ignore
+//! async fn handler() -> Sse<impl Stream<Item = Event>> {
+//!     Sse::new(stream::iter(vec![Event::data("hello").id("1")])).retry(Duration::from_secs(3))
+//! }
+//! ```
+
+use actix_rt::time::{delay_for, Delay, Instant};
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpRequest, HttpResponse, Responder};
+use futures::channel::mpsc;
+use futures::future::{ready, Ready};
+use futures::{SinkExt, Stream};
+use serde::Serialize;
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// 默认的 keep-alive 间隔
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// 一条消息，所有字段都为空时只输出一个空行
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    /// 只有数据的消息，客户端作为 `message` 事件处理
+    pub fn data(data: impl Into<String>) -> Self {
+        Event { data: Some(data.into()), ..Event::default() }
+    }
+
+    /// 数据为 JSON
+    pub fn json<T: Serialize>(data: &T) -> Result<Self, serde_json::Error> {
+        Ok(Event::data(serde_json::to_string(data)?))
+    }
+
+    /// 注释，客户端忽略
+    pub fn comment(comment: impl Into<String>) -> Self {
+        Event { comment: Some(comment.into()), ..Event::default() }
+    }
+
+    /// 客户端重连时通过 `Last-Event-ID` 请求头带上
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// 事件类型，客户端通过 `addEventListener(event, ...)` 接收
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// 客户端断线后等待多久重连
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+// id 和 event 中的换行会破坏消息格式，直接去掉
+fn single_line(s: &str) -> String {
+    s.chars().filter(|c| *c != '\n' && *c != '\r').collect()
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(comment) = &self.comment {
+            for line in comment.lines() {
+                writeln!(f, ": {}", line)?;
+            }
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", single_line(id))?;
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", single_line(event))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        if let Some(data) = &self.data {
+            // lines 会忽略末尾的换行，空字符串也需要输出一个 data 行
+            let mut lines = data.lines().peekable();
+            if lines.peek().is_none() {
+                f.write_str("data: \n")?;
+            }
+            for line in lines {
+                writeln!(f, "data: {}", line)?;
+            }
+        }
+        f.write_char('\n')
+    }
+}
+
+/// `text/event-stream` 响应
+pub struct Sse<S> {
+    stream: S,
+    keep_alive: Option<Duration>,
+    retry: Option<Duration>,
+}
+
+impl<S> Sse<S>
+where
+    S: Stream<Item = Event> + 'static,
+{
+    pub fn new(stream: S) -> Self {
+        Sse { stream, keep_alive: Some(DEFAULT_KEEP_ALIVE), retry: None }
+    }
+
+    /// 空闲多久后发送 keep-alive 注释
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    pub fn no_keep_alive(mut self) -> Self {
+        self.keep_alive = None;
+        self
+    }
+
+    /// 在第一条消息之前发送 `retry:`
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl<S> Responder for Sse<S>
+where
+    S: Stream<Item = Event> + 'static,
+{
+    type Error = Error;
+    type Future = Ready<Result<HttpResponse, Error>>;
+
+    fn respond_to(self, _: &HttpRequest) -> Self::Future {
+        let body = SseBody {
+            stream: Box::pin(self.stream),
+            keep_alive: self.keep_alive.map(|interval| (interval, delay_for(interval))),
+            first: self.retry.map(|retry| Event::default().retry(retry)),
+        };
+        ready(Ok(HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            // 关闭 nginx 的响应缓冲
+            .header("X-Accel-Buffering", "no")
+            .streaming(body)))
+    }
+}
+
+struct SseBody<S> {
+    stream: Pin<Box<S>>,
+    keep_alive: Option<(Duration, Delay)>,
+    first: Option<Event>,
+}
+
+impl<S> SseBody<S> {
+    fn chunk(&mut self, event: Event) -> Poll<Option<Result<Bytes, Error>>> {
+        if let Some((interval, delay)) = &mut self.keep_alive {
+            delay.reset(Instant::now() + *interval);
+        }
+        Poll::Ready(Some(Ok(Bytes::from(event.to_string()))))
+    }
+}
+
+impl<S: Stream<Item = Event>> Stream for SseBody<S> {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(event) = this.first.take() {
+            return this.chunk(event);
+        }
+        match this.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(event)) => return this.chunk(event),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+        let idle = match &mut this.keep_alive {
+            Some((_, delay)) => Pin::new(delay).poll(cx).is_ready(),
+            None => false,
+        };
+        if idle {
+            return this.chunk(Event::comment("keep-alive"));
+        }
+        Poll::Pending
+    }
+}
+
+/// 客户端已经断开
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disconnected;
+
+/// 在其他任务中发送事件，见 `channel`
+#[derive(Clone)]
+pub struct SseSender {
+    sender: mpsc::Sender<Event>,
+}
+
+impl SseSender {
+    /// 缓冲已满时等待，客户端断开后返回错误
+    pub async fn send(&mut self, event: Event) -> Result<(), Disconnected> {
+        self.sender.send(event).await.map_err(|_| Disconnected)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+/// 最多缓存 `buffer` 个事件，所有 `SseSender` drop 后响应结束
+pub fn channel(buffer: usize) -> (SseSender, Sse<mpsc::Receiver<Event>>) {
+    let (sender, receiver) = mpsc::channel(buffer);
+    (SseSender { sender }, Sse::new(receiver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App};
+    use futures::{stream, StreamExt};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_event_format() {
+        let event = Event::data("line1\nline2").id("1").event("created");
+        assert_eq!(event.to_string(), "id: 1\nevent: created\ndata: line1\ndata: line2\n\n");
+        assert_eq!(Event::data("").to_string(), "data: \n\n");
+        assert_eq!(Event::comment("keep-alive").to_string(), ": keep-alive\n\n");
+        assert_eq!(Event::default().retry(Duration::from_secs(3)).to_string(), "retry: 3000\n\n");
+        assert_eq!(Event::data("x").id("1\n2").event("a\r\nb").to_string(), "id: 12\nevent: ab\ndata: x\n\n");
+        assert_eq!(Event::json(&serde_json::json!({"a": 1})).unwrap().to_string(), "data: {\"a\":1}\n\n");
+    }
+
+    #[actix_rt::test]
+    async fn test_sse() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let producer_stopped = stopped.clone();
+        let srv = test::start(move || {
+            let stopped = producer_stopped.clone();
+            App::new()
+                .route("/finite", web::get().to(|| async {
+                    Sse::new(stream::iter(vec![Event::data("a").id("1"), Event::data("b").event("end")]))
+                        .retry(Duration::from_secs(1))
+                }))
+                .route("/idle", web::get().to(|| async {
+                    Sse::new(stream::pending::<Event>()).keep_alive(Duration::from_millis(100))
+                }))
+                .route("/ticks", web::get().to(move || {
+                    let stopped = stopped.clone();
+                    async move {
+                        let (mut sender, sse) = channel(1);
+                        actix_rt::spawn(async move {
+                            let mut i = 0;
+                            while sender.send(Event::data(i.to_string())).await.is_ok() {
+                                i += 1;
+                                actix_rt::time::delay_for(Duration::from_millis(20)).await;
+                            }
+                            stopped.store(true, Ordering::SeqCst);
+                        });
+                        sse.keep_alive(Duration::from_millis(100))
+                    }
+                }))
+        });
+
+        // stream 结束时响应结束
+        let mut resp = awc::Client::new().get(srv.url("/finite")).send().await.unwrap();
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/event-stream");
+        assert_eq!(resp.headers().get("cache-control").unwrap(), "no-cache");
+        let body = resp.body().await.unwrap();
+        assert_eq!(body, "retry: 1000\n\nid: 1\ndata: a\n\nevent: end\ndata: b\n\n");
+
+        let mut resp = awc::Client::new().get(srv.url("/idle")).send().await.unwrap();
+        let mut body = Vec::new();
+        while !body.ends_with(b": keep-alive\n\n: keep-alive\n\n") {
+            body.extend_from_slice(&resp.next().await.unwrap().unwrap());
+        }
+        assert_eq!(body, b": keep-alive\n\n: keep-alive\n\n");
+
+        // 客户端断开后生产者停止
+        let mut resp = awc::Client::new().get(srv.url("/ticks")).send().await.unwrap();
+        let chunk = resp.next().await.unwrap().unwrap();
+        assert!(chunk.starts_with(b"data: 0\n\n"));
+        assert!(!stopped.load(Ordering::SeqCst));
+        drop(resp);
+        for _ in 0..50 {
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        }
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,278 @@
+//! Bearer token（JWT）
+//!
+//! 供无法使用 cookie 的客户端（移动端、命令行）使用：
+//! 1. `POST /auth/token` 使用用户名和密码换取 access token 和 refresh token
+//! 2. 之后的请求带上 `Authorization: Bearer <access token>`，由 `middleware::BearerAuth` 验证
+//! 3. access token 过期后使用 refresh token 调用 `POST /auth/token/refresh` 换取新的一对 token，
+//!    旧的 refresh token 同时被撤销
+//!
+//! 撤销的 token 按 `jti` 记录在 `revoked_tokens` 表中，过期后删除。
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::settings::{JwtAlgorithm, JwtSettings, SettingsError, JWT_SECRET_MIN_LEN};
+use crate::{model, schema, AppError, DbConnection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    /// 访问接口
+    Access,
+    /// 只能用于换取新的 token
+    Refresh,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    /// 用户 id
+    pub sub: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    /// token 的唯一 id，撤销时使用
+    pub jti: String,
+    pub typ: TokenKind,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> Result<i64, AppError> {
+        self.sub.parse().map_err(|_| invalid_token())
+    }
+
+    pub fn expires_at(&self) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(self.exp, 0)
+    }
+}
+
+/// 验证通过的 Bearer token，由 `middleware::BearerAuth` 写入请求 extensions
+#[derive(Debug, Clone)]
+pub struct BearerIdentity {
+    pub user_id: i64,
+    pub claims: Claims,
+}
+
+/// `POST /auth/token` 和 `POST /auth/token/refresh` 的响应
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub token_type: &'static str,
+    /// access token 的有效期（秒）
+    pub expires_in: u64,
+    pub refresh_token: String,
+    pub refresh_expires_in: u64,
+}
+
+struct Inner {
+    header: Header,
+    encoding: EncodingKey,
+    decoding: DecodingKey<'static>,
+    validation: Validation,
+    issuer: String,
+    access_ttl: u64,
+    refresh_ttl: u64,
+}
+
+/// 签发和验证 token，clone 后共享同一份密钥
+#[derive(Clone)]
+pub struct Jwt {
+    inner: Arc<Inner>,
+}
+
+impl Jwt {
+    /// 读取密钥，HS256 没有配置密钥时随机生成
+    pub fn new(settings: &JwtSettings) -> Result<Self, SettingsError> {
+        let (algorithm, encoding, decoding) = match settings.algorithm {
+            JwtAlgorithm::HS256 => {
+                let secret = match &settings.secret {
+                    Some(secret) => secret.as_bytes().to_vec(),
+                    None => {
+                        log::warn!("jwt.secret is not set, using a random key");
+                        (0..JWT_SECRET_MIN_LEN).map(|_| rand::random::<u8>()).collect()
+                    }
+                };
+                (
+                    Algorithm::HS256,
+                    EncodingKey::from_secret(&secret),
+                    DecodingKey::from_secret(&secret).into_static(),
+                )
+            }
+            JwtAlgorithm::RS256 => {
+                let (encoding, decoding) = rsa_keys(settings)?;
+                (Algorithm::RS256, encoding, decoding)
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = settings.leeway;
+        validation.iss = Some(settings.issuer.clone());
+        Ok(Jwt {
+            inner: Arc::new(Inner {
+                header: Header::new(algorithm),
+                encoding,
+                decoding,
+                validation,
+                issuer: settings.issuer.clone(),
+                access_ttl: settings.access_ttl,
+                refresh_ttl: settings.refresh_ttl,
+            }),
+        })
+    }
+
+    pub fn issue(&self, user_id: i64, kind: TokenKind) -> Result<(String, Claims), AppError> {
+        let ttl = match kind {
+            TokenKind::Access => self.inner.access_ttl,
+            TokenKind::Refresh => self.inner.refresh_ttl,
+        };
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: user_id.to_string(),
+            iss: self.inner.issuer.clone(),
+            iat: now,
+            exp: now + ttl as i64,
+            jti: format!("{:032x}", rand::random::<u128>()),
+            typ: kind,
+        };
+        Ok((self.encode(&claims)?, claims))
+    }
+
+    pub fn issue_pair(&self, user_id: i64) -> Result<TokenPair, AppError> {
+        let (access_token, _) = self.issue(user_id, TokenKind::Access)?;
+        let (refresh_token, _) = self.issue(user_id, TokenKind::Refresh)?;
+        Ok(TokenPair {
+            access_token,
+            token_type: "Bearer",
+            expires_in: self.inner.access_ttl,
+            refresh_token,
+            refresh_expires_in: self.inner.refresh_ttl,
+        })
+    }
+
+    fn encode(&self, claims: &Claims) -> Result<String, AppError> {
+        jsonwebtoken::encode(&self.inner.header, claims, &self.inner.encoding).map_err(AppError::internal)
+    }
+
+    /// 验证签名、签发者和过期时间，kind 不一致时同样返回 401
+    pub fn decode(&self, token: &str, kind: TokenKind) -> Result<Claims, AppError> {
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.inner.decoding, &self.inner.validation)
+            .map(|data| data.claims)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => AppError::Unauthorized("token expired".to_string()),
+                _ => invalid_token(),
+            })?;
+        if claims.typ != kind {
+            return Err(invalid_token());
+        }
+        Ok(claims)
+    }
+}
+
+/// 检查密钥配置，RS256 读取并解析密钥文件；HS256 的密钥长度由 `Settings::validate` 检查
+pub fn check_keys(settings: &JwtSettings) -> Result<(), SettingsError> {
+    match settings.algorithm {
+        JwtAlgorithm::HS256 => Ok(()),
+        JwtAlgorithm::RS256 => rsa_keys(settings).map(|_| ()),
+    }
+}
+
+fn rsa_keys(settings: &JwtSettings) -> Result<(EncodingKey, DecodingKey<'static>), SettingsError> {
+    let private_key = read_key(settings.private_key.as_deref(), "jwt.private_key")?;
+    let public_key = read_key(settings.public_key.as_deref(), "jwt.public_key")?;
+    let encoding = EncodingKey::from_rsa_pem(&private_key)
+        .map_err(|e| SettingsError::Invalid(format!("jwt.private_key: {}", e)))?;
+    let decoding = DecodingKey::from_rsa_pem(&public_key)
+        .map_err(|e| SettingsError::Invalid(format!("jwt.public_key: {}", e)))?
+        .into_static();
+    Ok((encoding, decoding))
+}
+
+fn read_key(path: Option<&Path>, name: &str) -> Result<Vec<u8>, SettingsError> {
+    let path = path.ok_or_else(|| SettingsError::Invalid(format!("{} is required for RS256", name)))?;
+    fs::read(path).map_err(|e| SettingsError::File(format!("{}: {}", path.display(), e)))
+}
+
+fn invalid_token() -> AppError {
+    AppError::Unauthorized("invalid token".to_string())
+}
+
+pub fn is_revoked(conn: &DbConnection, jti: &str) -> QueryResult<bool> {
+    use schema::revoked_tokens;
+    revoked_tokens::table
+        .find(jti)
+        .select(revoked_tokens::jti)
+        .first::<String>(conn)
+        .optional()
+        .map(|t| t.is_some())
+}
+
+/// 撤销 token，已经撤销过时返回 false
+///
+/// 同时删除已经过期的记录，过期的 token 无论是否撤销都无法通过验证
+pub fn revoke(conn: &DbConnection, claims: &Claims) -> Result<bool, AppError> {
+    use schema::revoked_tokens;
+    let now = Utc::now().naive_utc();
+    diesel::delete(revoked_tokens::table.filter(revoked_tokens::expires_at.lt(now)))
+        .execute(conn)?;
+    let token = model::RevokedTokenForInsert {
+        jti: claims.jti.clone(),
+        user_id: claims.user_id()?,
+        expires_at: claims.expires_at(),
+    };
+    match diesel::insert_into(revoked_tokens::table).values(&token).execute(conn) {
+        Ok(_) => Ok(true),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hs256() {
+        let jwt = Jwt::new(&JwtSettings {
+            secret: Some("0123456789abcdef0123456789abcdef".to_string()),
+            ..JwtSettings::default()
+        }).unwrap();
+        let (token, claims) = jwt.issue(42, TokenKind::Access).unwrap();
+        assert_eq!(jwt.decode(&token, TokenKind::Access).unwrap(), claims);
+        assert_eq!(claims.user_id().unwrap(), 42);
+        // refresh token 不能用于访问接口
+        assert!(jwt.decode(&token, TokenKind::Refresh).is_err());
+
+        // 其他密钥签发的 token
+        let other = Jwt::new(&JwtSettings::default()).unwrap();
+        let (token, _) = other.issue(42, TokenKind::Access).unwrap();
+        assert_eq!(jwt.decode(&token, TokenKind::Access).unwrap_err().message(), "invalid token");
+
+        let expired = jwt.encode(&Claims { exp: claims.iat - 3600, ..claims }).unwrap();
+        assert_eq!(jwt.decode(&expired, TokenKind::Access).unwrap_err().message(), "token expired");
+    }
+
+    #[test]
+    fn test_rs256() {
+        let settings = JwtSettings {
+            algorithm: JwtAlgorithm::RS256,
+            private_key: Some("tests/keys/rs256_private.pem".into()),
+            public_key: Some("tests/keys/rs256_public.pem".into()),
+            ..JwtSettings::default()
+        };
+        let jwt = Jwt::new(&settings).unwrap();
+        let pair = jwt.issue_pair(7).unwrap();
+        assert_eq!(jwt.decode(&pair.refresh_token, TokenKind::Refresh).unwrap().user_id().unwrap(), 7);
+
+        // 签发者不一致
+        let verifier = Jwt::new(&JwtSettings { issuer: "other".to_string(), ..settings.clone() }).unwrap();
+        assert!(verifier.decode(&pair.access_token, TokenKind::Access).is_err());
+
+        assert!(Jwt::new(&JwtSettings { public_key: None, ..settings }).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+//! 请求追踪（调试用）
+//!
+//! `middleware::SayHi` 记录请求和响应的头、体和耗时，根据 `trace.output` 输出到日志
+//! 或保存在内存中的环形缓冲区，通过 `GET /debug/requests` 查看。
+//! 请求体和响应体在流经中间件时复制前 `max_body_size` 个字节，不会额外缓冲整个 body。
+
+use actix_web::http::HeaderMap;
+use actix_web::web::Bytes;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::settings::{TraceOutput, TraceSettings};
+
+/// 追踪日志的 target
+pub const TRACE_LOG_TARGET: &str = "actix_learn::trace";
+
+const REDACTED: &str = "***";
+
+/// 一个请求的追踪记录
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceRecord {
+    /// 进程内递增的序号
+    pub id: u64,
+    pub request_id: Option<String>,
+    pub time: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub status: u16,
+    /// 从收到请求到响应头生成的耗时
+    pub latency_ms: f64,
+    pub request_headers: Option<BTreeMap<String, String>>,
+    pub request_body: Option<CapturedBody>,
+    pub response_headers: Option<BTreeMap<String, String>>,
+    pub response_body: Option<CapturedBody>,
+}
+
+impl TraceRecord {
+    pub fn new(method: &str, path: &str, query: &str) -> Self {
+        TraceRecord {
+            id: 0,
+            request_id: None,
+            time: Utc::now(),
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            status: 0,
+            latency_ms: 0.0,
+            request_headers: None,
+            request_body: None,
+            response_headers: None,
+            response_body: None,
+        }
+    }
+}
+
+/// 记录的 body
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CapturedBody {
+    /// 实际读取到的字节数
+    pub size: usize,
+    /// 超过 max_body_size 时只记录前面的部分
+    pub truncated: bool,
+    pub content: String,
+}
+
+/// 复制流经的 body 的前 limit 个字节
+#[derive(Debug)]
+pub struct BodyCapture {
+    limit: usize,
+    buf: Vec<u8>,
+    size: usize,
+}
+
+impl BodyCapture {
+    pub fn new(limit: usize) -> Self {
+        BodyCapture { limit, buf: Vec::new(), size: 0 }
+    }
+
+    pub fn push(&mut self, chunk: &Bytes) {
+        self.size += chunk.len();
+        let remaining = self.limit.saturating_sub(self.buf.len());
+        self.buf.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+    }
+}
+
+struct Inner {
+    settings: TraceSettings,
+    enabled: AtomicBool,
+    next_id: AtomicU64,
+    buffer: Mutex<VecDeque<TraceRecord>>,
+}
+
+/// 追踪配置和最近的记录，clone 后共享同一份数据
+#[derive(Clone)]
+pub struct Tracer {
+    inner: Arc<Inner>,
+}
+
+impl Tracer {
+    pub fn new(settings: TraceSettings) -> Self {
+        Tracer {
+            inner: Arc::new(Inner {
+                enabled: AtomicBool::new(settings.enabled),
+                next_id: AtomicU64::new(1),
+                buffer: Mutex::new(VecDeque::with_capacity(settings.buffer_size)),
+                settings,
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> &TraceSettings {
+        &self.inner.settings
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::Relaxed)
+    }
+
+    /// 运行时开关，关闭后已有的记录保留
+    pub fn set_enabled(&self, enabled: bool) {
+        self.inner.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 是否追踪该路径
+    pub fn should_trace(&self, path: &str) -> bool {
+        let settings = self.settings();
+        self.is_enabled()
+            && (settings.include.is_empty() || settings.include.iter().any(|p| glob_match(p, path)))
+            && !settings.exclude.iter().any(|p| glob_match(p, path))
+    }
+
+    /// 请求头或响应头，同名的多个值以 `, ` 连接
+    pub fn headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        let mut map = BTreeMap::<String, String>::new();
+        for (name, value) in headers.iter() {
+            let redacted = self.settings().redact_headers.iter().any(|h| h.eq_ignore_ascii_case(name.as_str()));
+            let value = if redacted {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            map.entry(name.as_str().to_string())
+                .and_modify(|v| {
+                    if !redacted {
+                        v.push_str(", ");
+                        v.push_str(&value);
+                    }
+                })
+                .or_insert(value);
+        }
+        map
+    }
+
+    /// 查询字符串，与表单一样隐藏敏感字段
+    pub fn query(&self, query: &str) -> String {
+        redact_form(query, &self.settings().redact_fields)
+    }
+
+    /// 按 Content-Type 隐藏敏感字段，无法识别为文本时只记录大小
+    pub fn body(&self, capture: &BodyCapture, content_type: Option<&str>) -> CapturedBody {
+        let text = String::from_utf8_lossy(&capture.buf);
+        let content_type = content_type.unwrap_or("").to_lowercase();
+        let fields = &self.settings().redact_fields;
+        let content = if content_type.contains("json") {
+            redact_json(&text, fields)
+        } else if content_type.starts_with("application/x-www-form-urlencoded") {
+            redact_form(&text, fields)
+        } else if content_type.is_empty() || content_type.starts_with("text/") {
+            text.into_owned()
+        } else {
+            format!("<{} bytes {}>", capture.size, content_type)
+        };
+        CapturedBody {
+            size: capture.size,
+            truncated: capture.size > capture.buf.len(),
+            content,
+        }
+    }
+
+    /// 输出一条记录
+    pub fn record(&self, mut record: TraceRecord) {
+        record.id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let output = self.settings().output;
+        if output != TraceOutput::Buffer {
+            log::info!(target: TRACE_LOG_TARGET, "{}", serde_json::to_string(&record).unwrap_or_default());
+        }
+        if output != TraceOutput::Log {
+            let mut buffer = self.inner.buffer.lock().unwrap();
+            while buffer.len() >= self.settings().buffer_size.max(1) {
+                buffer.pop_front();
+            }
+            buffer.push_back(record);
+        }
+    }
+
+    /// 最近的 limit 条记录，新的在前
+    pub fn recent(&self, limit: usize) -> Vec<TraceRecord> {
+        self.inner.buffer.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    /// 缓冲区中的记录数
+    pub fn buffered(&self) -> usize {
+        self.inner.buffer.lock().unwrap().len()
+    }
+
+    pub fn clear(&self) {
+        self.inner.buffer.lock().unwrap().clear();
+    }
+}
+
+/// `*` 匹配任意字符（包括 `/`），其他字符需要完全相同
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // split 至少返回一项
+    let first = parts.next().unwrap_or("");
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut rest = &path[first.len()..];
+    let parts = parts.collect::<Vec<_>>();
+    let last = match parts.split_last() {
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(i) => rest = &rest[i + part.len()..],
+                    None => return false,
+                }
+            }
+            last
+        }
+        // 没有通配符
+        None => return rest.is_empty(),
+    };
+    rest.ends_with(last)
+}
+
+// 不完整（被截断）的 JSON 也需要处理，所以没有先解析再序列化，而是直接替换文本中的字段值
+fn redact_json(text: &str, fields: &[String]) -> String {
+    let b = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] != b'"' {
+            i += 1;
+            continue;
+        }
+        let end = string_end(b, i + 1);
+        let key = &text[i + 1..end];
+        let mut j = skip_whitespace(b, end + 1);
+        if j < b.len() && b[j] == b':' && fields.iter().any(|f| f.eq_ignore_ascii_case(key)) {
+            j = skip_whitespace(b, j + 1);
+            let value_end = if j < b.len() && b[j] == b'"' {
+                (string_end(b, j + 1) + 1).min(b.len())
+            } else {
+                (j..b.len()).find(|k| matches!(b[*k], b',' | b'}' | b']')).unwrap_or(b.len())
+            };
+            out.push_str(&text[copied..j]);
+            out.push_str("\"***\"");
+            copied = value_end;
+            i = value_end;
+        } else {
+            i = end + 1;
+        }
+    }
+    if copied < text.len() {
+        out.push_str(&text[copied..]);
+    }
+    out
+}
+
+// 字符串结束的引号的位置，没有结束时返回长度
+fn string_end(b: &[u8], mut i: usize) -> usize {
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'"' => return i,
+            _ => i += 1,
+        }
+    }
+    b.len()
+}
+
+fn skip_whitespace(b: &[u8], mut i: usize) -> usize {
+    while i < b.len() && b[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn redact_form(text: &str, fields: &[String]) -> String {
+    text.split('&')
+        .map(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            if fields.iter().any(|f| f.eq_ignore_ascii_case(key)) {
+                format!("{}={}", key, REDACTED)
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Vec<String> {
+        TraceSettings::default().redact_fields
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("/metrics", "/metrics"));
+        assert!(!glob_match("/metrics", "/metrics/x"));
+        assert!(glob_match("/debug/*", "/debug/requests"));
+        assert!(!glob_match("/debug/*", "/debugger"));
+        assert!(glob_match("/users*", "/users"));
+        assert!(glob_match("/users/*/posts", "/users/1/posts"));
+        assert!(!glob_match("/users/*/posts", "/users/1/comments"));
+        assert!(glob_match("*", "/anything"));
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            redact_json(r#"{"name": "a", "Password": "p\"w", "token":12, "tags":["token"]}"#, &fields()),
+            r#"{"name": "a", "Password": "***", "token":"***", "tags":["token"]}"#
+        );
+        // 被截断的 JSON
+        assert_eq!(redact_json(r#"{"secret": "abc"#, &fields()), r#"{"secret": "***""#);
+        assert_eq!(redact_form("name=a&password=b&x", &fields()), "name=a&password=***&x");
+    }
+
+    #[test]
+    fn test_tracer() {
+        let tracer = Tracer::new(TraceSettings {
+            enabled: true,
+            max_body_size: 8,
+            buffer_size: 2,
+            ..TraceSettings::default()
+        });
+        assert!(tracer.should_trace("/users"));
+        assert!(!tracer.should_trace("/debug/requests"));
+        tracer.set_enabled(false);
+        assert!(!tracer.should_trace("/users"));
+
+        let mut capture = BodyCapture::new(8);
+        capture.push(&Bytes::from_static(b"0123456789"));
+        let body = tracer.body(&capture, Some("text/plain"));
+        assert_eq!(body, CapturedBody { size: 10, truncated: true, content: "01234567".to_string() });
+        assert_eq!(tracer.body(&capture, Some("image/png")).content, "<10 bytes image/png>");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie".parse().unwrap(), "a=b".parse().unwrap());
+        headers.insert("accept".parse().unwrap(), "*/*".parse().unwrap());
+        let headers = tracer.headers(&headers);
+        assert_eq!(headers["cookie"], "***");
+        assert_eq!(headers["accept"], "*/*");
+        assert_eq!(tracer.query("limit=10&access_token=abc"), "limit=10&access_token=***");
+
+        for path in &["/a", "/b", "/c"] {
+            tracer.record(TraceRecord::new("GET", path, ""));
+        }
+        let recent = tracer.recent(10);
+        assert_eq!(recent.iter().map(|r| r.path.as_str()).collect::<Vec<_>>(), vec!["/c", "/b"]);
+        assert_eq!(recent[0].id, 3);
+    }
+}